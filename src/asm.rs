@@ -0,0 +1,528 @@
+//! A two-pass LC-3 assembler: lex source into per-line statements, build a
+//! symbol table of label addresses in pass one, then in pass two resolve
+//! operands (including PC-relative label references) into `Opcode` values
+//! and bit-pack them via `Opcode::encode`. The output is a `(origin,
+//! words...)` byte buffer in exactly the shape `VM::new`'s loader
+//! (`read_data_into_memory`) consumes, so assembling and running compose
+//! cleanly.
+
+use crate::opcodes::{Argument, Opcode};
+use std::collections::HashMap;
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum AsmError {
+    #[error("line {line}: unknown mnemonic '{mnemonic}'")]
+    UnknownMnemonic { line: usize, mnemonic: String },
+    #[error("line {line}: duplicate label '{label}'")]
+    DuplicateLabel { line: usize, label: String },
+    #[error("line {line}: undefined label '{label}'")]
+    UndefinedLabel { line: usize, label: String },
+    #[error("line {line}: value {value} does not fit in {bits} bits")]
+    OffsetOutOfRange { line: usize, value: i32, bits: u32 },
+    #[error("line {line}: missing .ORIG directive")]
+    MissingOrig { line: usize },
+    #[error("line {0}: {1}")]
+    Syntax(usize, String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Register(usize),
+    Immediate(i32),
+    Str(String),
+    Label(String),
+}
+
+struct Statement {
+    line: usize,
+    label: Option<String>,
+    /// The directive or mnemonic, uppercased. `None` for a label-only line.
+    op: Option<String>,
+    args: Vec<Token>,
+}
+
+/// Assembles `source` into a byte buffer: a big-endian origin word followed
+/// by big-endian instruction/data words, matching what `VM::new`'s loader
+/// expects.
+pub(crate) fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    let statements = lex(source)?;
+    let (origin, symbols) = first_pass(&statements)?;
+    let words = second_pass(&statements, origin, &symbols)?;
+
+    let mut bytes = Vec::with_capacity((words.len() + 1) * 2);
+    bytes.push((origin >> 8) as u8);
+    bytes.push((origin & 0xFF) as u8);
+    for word in words {
+        bytes.push((word >> 8) as u8);
+        bytes.push((word & 0xFF) as u8);
+    }
+    Ok(bytes)
+}
+
+fn lex(source: &str) -> Result<Vec<Statement>, AsmError> {
+    let mut statements = Vec::new();
+    for (i, raw_line) in source.lines().enumerate() {
+        let line = i + 1;
+        let text = strip_comment(raw_line).trim();
+        if text.is_empty() {
+            continue;
+        }
+
+        let mut words = split_words(text);
+        let label = if is_directive_or_mnemonic(&words[0]) {
+            None
+        } else {
+            Some(words.remove(0))
+        };
+        if words.is_empty() {
+            statements.push(Statement { line, label, op: None, args: Vec::new() });
+            continue;
+        }
+
+        let op = words.remove(0).to_ascii_uppercase();
+        let args = words
+            .iter()
+            .map(|w| parse_operand(w))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|msg| AsmError::Syntax(line, msg))?;
+        statements.push(Statement { line, label, op: Some(op), args });
+    }
+    Ok(statements)
+}
+
+/// Strips a `;`-to-end-of-line comment, ignoring `;` inside a `"..."` string.
+fn strip_comment(line: &str) -> &str {
+    let mut in_quotes = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            ';' if !in_quotes => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Splits a line on whitespace/commas, keeping `"..."` strings intact.
+fn split_words(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    for c in line.chars() {
+        if in_quotes {
+            current.push(c);
+            if c == '"' {
+                in_quotes = false;
+                words.push(std::mem::take(&mut current));
+            }
+            continue;
+        }
+        match c {
+            '"' => {
+                in_quotes = true;
+                current.push(c);
+            }
+            ',' | ' ' | '\t' => {
+                if !current.is_empty() {
+                    words.push(std::mem::take(&mut current));
+                }
+            }
+            _ => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+const DIRECTIVES: [&str; 5] = [".ORIG", ".FILL", ".BLKW", ".STRINGZ", ".END"];
+const MNEMONICS: [&str; 22] = [
+    "ADD", "AND", "NOT", "JMP", "RET", "JSR", "JSRR", "LD", "LDI", "LDR", "LEA", "ST", "STI",
+    "STR", "RTI", "TRAP", "GETC", "OUT", "PUTS", "IN", "PUTSP", "HALT",
+];
+
+fn is_directive_or_mnemonic(word: &str) -> bool {
+    let upper = word.to_ascii_uppercase();
+    if DIRECTIVES.contains(&upper.as_str()) || MNEMONICS.contains(&upper.as_str()) {
+        return true;
+    }
+    match upper.strip_prefix("BR") {
+        Some(suffix) => suffix.chars().all(|c| matches!(c, 'N' | 'Z' | 'P')),
+        None => false,
+    }
+}
+
+fn parse_operand(word: &str) -> Result<Token, String> {
+    if word.starts_with('"') {
+        return word
+            .strip_prefix('"')
+            .and_then(|s| s.strip_suffix('"'))
+            .map(|s| Token::Str(s.to_string()))
+            .ok_or_else(|| format!("unterminated string {word}"));
+    }
+    let upper = word.to_ascii_uppercase();
+    if let Some(rest) = upper.strip_prefix('R') {
+        if let Ok(r) = rest.parse::<usize>() {
+            if r <= 7 {
+                return Ok(Token::Register(r));
+            }
+        }
+    }
+    if let Some(rest) = word.strip_prefix('#') {
+        return rest
+            .parse::<i32>()
+            .map(Token::Immediate)
+            .map_err(|_| format!("invalid decimal immediate {word}"));
+    }
+    if let Some(rest) = upper.strip_prefix('X') {
+        return i32::from_str_radix(rest, 16)
+            .map(Token::Immediate)
+            .map_err(|_| format!("invalid hex immediate {word}"));
+    }
+    if let Ok(n) = word.parse::<i32>() {
+        return Ok(Token::Immediate(n));
+    }
+    Ok(Token::Label(word.to_string()))
+}
+
+fn first_pass(statements: &[Statement]) -> Result<(u16, HashMap<String, u16>), AsmError> {
+    let mut origin = None;
+    let mut addr = 0u16;
+    let mut symbols = HashMap::new();
+
+    for stmt in statements {
+        match stmt.op.as_deref() {
+            Some(".ORIG") => {
+                let value = expect_imm(&stmt.args, 0, stmt.line)?;
+                origin = Some(value as u16);
+                addr = value as u16;
+                continue;
+            }
+            Some(".END") => break,
+            _ => {}
+        }
+        let Some(_) = origin else {
+            return Err(AsmError::MissingOrig { line: stmt.line });
+        };
+        if let Some(label) = &stmt.label {
+            if symbols.insert(label.clone(), addr).is_some() {
+                return Err(AsmError::DuplicateLabel { line: stmt.line, label: label.clone() });
+            }
+        }
+        addr = addr.wrapping_add(statement_size(stmt)?);
+    }
+
+    let origin = origin.ok_or(AsmError::MissingOrig { line: 0 })?;
+    Ok((origin, symbols))
+}
+
+fn statement_size(stmt: &Statement) -> Result<u16, AsmError> {
+    match stmt.op.as_deref() {
+        None => Ok(0),
+        Some(".BLKW") => Ok(expect_imm(&stmt.args, 0, stmt.line)? as u16),
+        Some(".STRINGZ") => Ok(expect_str(&stmt.args, 0, stmt.line)?.len() as u16 + 1),
+        Some(_) => Ok(1),
+    }
+}
+
+fn second_pass(
+    statements: &[Statement],
+    origin: u16,
+    symbols: &HashMap<String, u16>,
+) -> Result<Vec<u16>, AsmError> {
+    let mut words = Vec::new();
+    let mut addr = origin;
+
+    for stmt in statements {
+        match stmt.op.as_deref() {
+            Some(".ORIG") => {
+                addr = expect_imm(&stmt.args, 0, stmt.line)? as u16;
+                continue;
+            }
+            Some(".END") => break,
+            Some(".FILL") => {
+                words.push(resolve_fill(&stmt.args, symbols, stmt.line)?);
+                addr = addr.wrapping_add(1);
+            }
+            Some(".BLKW") => {
+                let n = expect_imm(&stmt.args, 0, stmt.line)? as u16;
+                words.resize(words.len() + n as usize, 0);
+                addr = addr.wrapping_add(n);
+            }
+            Some(".STRINGZ") => {
+                let s = expect_str(&stmt.args, 0, stmt.line)?;
+                for byte in s.bytes() {
+                    words.push(byte as u16);
+                }
+                words.push(0);
+                addr = addr.wrapping_add(s.len() as u16 + 1);
+            }
+            None => {}
+            Some(op) => {
+                let opcode = build_opcode(op, &stmt.args, addr, symbols, stmt.line)?;
+                words.push(opcode.encode());
+                addr = addr.wrapping_add(1);
+            }
+        }
+    }
+    Ok(words)
+}
+
+fn expect_reg(args: &[Token], idx: usize, line: usize) -> Result<usize, AsmError> {
+    match args.get(idx) {
+        Some(Token::Register(r)) => Ok(*r),
+        _ => Err(AsmError::Syntax(line, format!("expected a register operand at position {idx}"))),
+    }
+}
+
+fn expect_imm(args: &[Token], idx: usize, line: usize) -> Result<i32, AsmError> {
+    match args.get(idx) {
+        Some(Token::Immediate(v)) => Ok(*v),
+        _ => Err(AsmError::Syntax(line, format!("expected an immediate value at position {idx}"))),
+    }
+}
+
+fn expect_str(args: &[Token], idx: usize, line: usize) -> Result<&str, AsmError> {
+    match args.get(idx) {
+        Some(Token::Str(s)) => Ok(s),
+        _ => Err(AsmError::Syntax(line, format!("expected a string literal at position {idx}"))),
+    }
+}
+
+fn check_range(value: i32, bits: u32, line: usize) -> Result<i16, AsmError> {
+    let lo = -(1i32 << (bits - 1));
+    let hi = (1i32 << (bits - 1)) - 1;
+    if value < lo || value > hi {
+        return Err(AsmError::OffsetOutOfRange { line, value, bits });
+    }
+    Ok(value as i16)
+}
+
+/// Resolves a plain (non-PC-relative) immediate operand, e.g. `ADD`/`AND`'s
+/// imm5 or `LDR`/`STR`'s offset6.
+fn resolve_imm(token: &Token, bits: u32, line: usize) -> Result<i16, AsmError> {
+    match token {
+        Token::Immediate(v) => check_range(*v, bits, line),
+        _ => Err(AsmError::Syntax(line, "expected an immediate operand".to_string())),
+    }
+}
+
+/// Resolves a PC-relative operand (immediate or label) for `BR`/`JSR`/
+/// `LD`/`LDI`/`LEA`/`ST`/`STI`, relative to the instruction at `addr`.
+fn resolve_pc_offset(
+    token: &Token,
+    addr: u16,
+    symbols: &HashMap<String, u16>,
+    bits: u32,
+    line: usize,
+) -> Result<i16, AsmError> {
+    let value = match token {
+        Token::Immediate(v) => *v,
+        Token::Label(name) => {
+            let target = *symbols
+                .get(name)
+                .ok_or_else(|| AsmError::UndefinedLabel { line, label: name.clone() })?;
+            target.wrapping_sub(addr.wrapping_add(1)) as i16 as i32
+        }
+        _ => return Err(AsmError::Syntax(line, "expected an immediate or label operand".to_string())),
+    };
+    check_range(value, bits, line)
+}
+
+fn resolve_trap_vector(token: &Token, line: usize) -> Result<u8, AsmError> {
+    match token {
+        Token::Immediate(v) if (0..=0xFF).contains(v) => Ok(*v as u8),
+        Token::Immediate(v) => Err(AsmError::OffsetOutOfRange { line, value: *v, bits: 8 }),
+        _ => Err(AsmError::Syntax(line, "expected an 8-bit trap vector".to_string())),
+    }
+}
+
+fn resolve_fill(args: &[Token], symbols: &HashMap<String, u16>, line: usize) -> Result<u16, AsmError> {
+    match args.first() {
+        Some(Token::Immediate(v)) => Ok(*v as u16),
+        Some(Token::Label(name)) => symbols
+            .get(name)
+            .copied()
+            .ok_or_else(|| AsmError::UndefinedLabel { line, label: name.clone() }),
+        _ => Err(AsmError::Syntax(line, "expected an immediate or label operand".to_string())),
+    }
+}
+
+fn build_opcode(
+    op: &str,
+    args: &[Token],
+    addr: u16,
+    symbols: &HashMap<String, u16>,
+    line: usize,
+) -> Result<Opcode, AsmError> {
+    match op {
+        "ADD" | "AND" => {
+            let dr = expect_reg(args, 0, line)?;
+            let sr1 = expect_reg(args, 1, line)?;
+            let sr2 = match args.get(2) {
+                Some(Token::Register(r)) => Argument::Reg(*r),
+                Some(tok) => Argument::Immediate(resolve_imm(tok, 5, line)?),
+                None => return Err(AsmError::Syntax(line, format!("{op} requires 3 operands"))),
+            };
+            Ok(if op == "ADD" {
+                Opcode::ADD { dr, sr1, sr2 }
+            } else {
+                Opcode::AND { dr, sr1, sr2 }
+            })
+        }
+        "NOT" => Ok(Opcode::NOT { dr: expect_reg(args, 0, line)?, sr: expect_reg(args, 1, line)? }),
+        "JMP" => Ok(Opcode::JMP { base_r: expect_reg(args, 0, line)? }),
+        "RET" => Ok(Opcode::RET),
+        "JSRR" => Ok(Opcode::JSRR { base_r: expect_reg(args, 0, line)? }),
+        "JSR" => {
+            let offset = resolve_pc_offset(args.first().ok_or_missing(line)?, addr, symbols, 11, line)?;
+            Ok(Opcode::JSR { offset })
+        }
+        "LD" | "LDI" | "LEA" => {
+            let dr = expect_reg(args, 0, line)?;
+            let target = args.get(1).ok_or_missing(line)?;
+            let offset = resolve_pc_offset(target, addr, symbols, 9, line)?;
+            Ok(match op {
+                "LD" => Opcode::LD { dr, offset },
+                "LDI" => Opcode::LDI { dr, offset },
+                _ => Opcode::LEA { dr, offset },
+            })
+        }
+        "ST" | "STI" => {
+            let sr = expect_reg(args, 0, line)?;
+            let target = args.get(1).ok_or_missing(line)?;
+            let offset = resolve_pc_offset(target, addr, symbols, 9, line)?;
+            Ok(if op == "ST" {
+                Opcode::ST { sr, offset }
+            } else {
+                Opcode::STI { sr, offset }
+            })
+        }
+        "LDR" => Ok(Opcode::LDR {
+            dr: expect_reg(args, 0, line)?,
+            base_r: expect_reg(args, 1, line)?,
+            offset: resolve_imm(args.get(2).ok_or_missing(line)?, 6, line)?,
+        }),
+        "STR" => Ok(Opcode::STR {
+            sr: expect_reg(args, 0, line)?,
+            base_r: expect_reg(args, 1, line)?,
+            offset: resolve_imm(args.get(2).ok_or_missing(line)?, 6, line)?,
+        }),
+        "RTI" => Ok(Opcode::RTI),
+        "TRAP" => Ok(Opcode::TRAP { vector: resolve_trap_vector(args.first().ok_or_missing(line)?, line)? }),
+        "GETC" => Ok(Opcode::TRAP { vector: 0x20 }),
+        "OUT" => Ok(Opcode::TRAP { vector: 0x21 }),
+        "PUTS" => Ok(Opcode::TRAP { vector: 0x22 }),
+        "IN" => Ok(Opcode::TRAP { vector: 0x23 }),
+        "PUTSP" => Ok(Opcode::TRAP { vector: 0x24 }),
+        "HALT" => Ok(Opcode::TRAP { vector: 0x25 }),
+        _ if op.starts_with("BR") => {
+            let suffix = &op[2..];
+            let (n, z, p) = if suffix.is_empty() {
+                (true, true, true)
+            } else {
+                (suffix.contains('N'), suffix.contains('Z'), suffix.contains('P'))
+            };
+            let offset = resolve_pc_offset(args.first().ok_or_missing(line)?, addr, symbols, 9, line)?;
+            Ok(Opcode::BR { n, z, p, offset })
+        }
+        _ => Err(AsmError::UnknownMnemonic { line, mnemonic: op.to_string() }),
+    }
+}
+
+trait OrMissingOperand<T> {
+    fn ok_or_missing(self, line: usize) -> Result<T, AsmError>;
+}
+
+impl<'a> OrMissingOperand<&'a Token> for Option<&'a Token> {
+    fn ok_or_missing(self, line: usize) -> Result<&'a Token, AsmError> {
+        self.ok_or_else(|| AsmError::Syntax(line, "missing operand".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assembles_add_and_halt() {
+        let bytes = assemble(
+            "\
+            .ORIG x3000\n\
+            ADD R0, R0, #5\n\
+            HALT\n\
+            .END\n",
+        )
+        .unwrap();
+        assert_eq!(bytes[0..2], [0x30, 0x00]);
+        let add = Opcode::ADD { dr: 0, sr1: 0, sr2: Argument::Immediate(5) };
+        let halt = Opcode::TRAP { vector: 0x25 };
+        assert_eq!(bytes[2..4], add.encode().to_be_bytes());
+        assert_eq!(bytes[4..6], halt.encode().to_be_bytes());
+    }
+
+    #[test]
+    fn resolves_forward_and_backward_label_references() {
+        let bytes = assemble(
+            "\
+            .ORIG x3000\n\
+            LOOP ADD R0, R0, #1\n\
+            BRz DONE\n\
+            BRnzp LOOP\n\
+            DONE HALT\n\
+            .END\n",
+        )
+        .unwrap();
+        let forward = Opcode::BR { n: false, z: true, p: false, offset: 1 };
+        assert_eq!(bytes[4..6], forward.encode().to_be_bytes());
+        let backward = Opcode::BR { n: true, z: true, p: true, offset: -3 };
+        assert_eq!(bytes[6..8], backward.encode().to_be_bytes());
+    }
+
+    #[test]
+    fn assembles_fill_blkw_and_stringz() {
+        let bytes = assemble(
+            "\
+            .ORIG x3000\n\
+            .FILL x1234\n\
+            .BLKW 2\n\
+            .STRINGZ \"hi\"\n\
+            .END\n",
+        )
+        .unwrap();
+        assert_eq!(&bytes[2..], &[
+            0x12, 0x34, // .FILL
+            0x00, 0x00, 0x00, 0x00, // .BLKW 2
+            0x00, b'h', 0x00, b'i', 0x00, 0x00, // .STRINGZ "hi"
+        ]);
+    }
+
+    #[test]
+    fn reports_unknown_mnemonic() {
+        let err = assemble(".ORIG x3000\nSTART FROB R0\n").unwrap_err();
+        assert_eq!(
+            err,
+            AsmError::UnknownMnemonic { line: 2, mnemonic: "FROB".to_string() }
+        );
+    }
+
+    #[test]
+    fn reports_duplicate_labels() {
+        let err = assemble(".ORIG x3000\nLOOP ADD R0,R0,#1\nLOOP ADD R0,R0,#1\n").unwrap_err();
+        assert_eq!(err, AsmError::DuplicateLabel { line: 3, label: "LOOP".to_string() });
+    }
+
+    #[test]
+    fn reports_undefined_labels() {
+        let err = assemble(".ORIG x3000\nBR NOWHERE\n").unwrap_err();
+        assert_eq!(err, AsmError::UndefinedLabel { line: 2, label: "NOWHERE".to_string() });
+    }
+
+    #[test]
+    fn reports_out_of_range_offsets() {
+        let err = assemble(".ORIG x3000\nADD R0, R0, #100\n").unwrap_err();
+        assert_eq!(err, AsmError::OffsetOutOfRange { line: 2, value: 100, bits: 5 });
+    }
+}
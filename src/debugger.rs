@@ -0,0 +1,197 @@
+//! Interactive REPL for `--debug`: single-step, breakpoints/watchpoints,
+//! and register/memory inspection on top of the `vm` module's `step` API.
+
+use crate::opcodes::Opcode;
+use crate::vm::{StepOutcome, VM};
+use std::io::{self, Write};
+
+const REG_NAMES: [&str; 9] = ["R0", "R1", "R2", "R3", "R4", "R5", "R6", "R7", "PC"];
+
+pub struct Debugger {
+    vm: VM,
+}
+
+impl Debugger {
+    pub fn new(vm: VM) -> Self {
+        Self { vm }
+    }
+
+    pub fn run(&mut self) {
+        println!("lc3-vm debugger. Type 'help' for a list of commands.");
+        loop {
+            if !self.vm.is_running() {
+                println!("Program halted.");
+                break;
+            }
+
+            print!("(lc3dbg) ");
+            io::stdout().flush().ok();
+            let mut line = String::new();
+            if io::stdin().read_line(&mut line).unwrap_or(0) == 0 {
+                break;
+            }
+            if !self.dispatch(line.trim()) {
+                break;
+            }
+        }
+    }
+
+    /// Returns `false` when the REPL should exit.
+    fn dispatch(&mut self, line: &str) -> bool {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("step") | Some("s") => self.cmd_step(),
+            Some("continue") | Some("c") => self.cmd_continue(),
+            Some("break") | Some("b") => self.cmd_break(words.next()),
+            Some("watch") | Some("w") => self.cmd_watch(words.next()),
+            Some("trace") => self.cmd_trace(words.next()),
+            Some("regs") => self.cmd_regs(),
+            Some("mem") => self.cmd_mem(words.next(), words.next()),
+            Some("disas") => self.cmd_disas(words.next(), words.next()),
+            Some("help") | Some("h") => print_help(),
+            Some("quit") | Some("q") => return false,
+            Some(other) => println!("unknown command: {other} (try 'help')"),
+            None => {}
+        }
+        true
+    }
+
+    fn cmd_step(&mut self) {
+        match self.vm.step() {
+            Ok(outcome) => print_outcome(&outcome),
+            Err(err) => println!("error: {err}"),
+        }
+    }
+
+    fn cmd_continue(&mut self) {
+        if !self.vm.is_running() {
+            return;
+        }
+        // `run_until_break` steps before checking breakpoints, so a
+        // breakpoint at the current PC doesn't re-trigger without progress.
+        match self.vm.run_until_break() {
+            Ok(outcome) => {
+                print_outcome(&outcome);
+                if self.vm.is_running() {
+                    if self.vm.has_breakpoint(outcome.pc_after) {
+                        println!("Breakpoint hit at {:#06x}", outcome.pc_after);
+                    } else {
+                        println!("Watchpoint hit.");
+                    }
+                }
+            }
+            Err(err) => println!("error: {err}"),
+        }
+    }
+
+    fn cmd_break(&mut self, addr: Option<&str>) {
+        match addr.and_then(parse_addr) {
+            Some(addr) => {
+                self.vm.add_breakpoint(addr);
+                println!("Breakpoint set at {addr:#06x}");
+            }
+            None => println!("usage: break <addr>"),
+        }
+    }
+
+    fn cmd_watch(&mut self, addr: Option<&str>) {
+        match addr.and_then(parse_addr) {
+            Some(addr) => {
+                self.vm.add_watchpoint(addr);
+                println!("Watchpoint set at {addr:#06x}");
+            }
+            None => println!("usage: watch <addr>"),
+        }
+    }
+
+    fn cmd_trace(&mut self, setting: Option<&str>) {
+        match setting {
+            Some("on") => {
+                self.vm.set_trace(true);
+                println!("Tracing enabled.");
+            }
+            Some("off") => {
+                self.vm.set_trace(false);
+                println!("Tracing disabled.");
+            }
+            _ => println!("usage: trace <on|off>"),
+        }
+    }
+
+    fn cmd_regs(&self) {
+        let r = self.vm.registers_snapshot();
+        println!(
+            "R0={:#06x} R1={:#06x} R2={:#06x} R3={:#06x} R4={:#06x} R5={:#06x} R6={:#06x} R7={:#06x} PC={:#06x} PSR={:#06x}",
+            r[0], r[1], r[2], r[3], r[4], r[5], r[6], r[7], r[8], self.vm.psr()
+        );
+    }
+
+    fn cmd_mem(&self, addr: Option<&str>, count: Option<&str>) {
+        let addr = addr.and_then(parse_addr);
+        let count = count.and_then(|c| c.parse::<u16>().ok()).unwrap_or(1);
+        match addr {
+            Some(addr) => {
+                for offset in 0..count {
+                    let a = addr.wrapping_add(offset);
+                    println!("{:#06x}: {:#06x}", a, self.vm.mem_read(a as usize));
+                }
+            }
+            None => println!("usage: mem <addr> [count]"),
+        }
+    }
+
+    fn cmd_disas(&self, addr: Option<&str>, count: Option<&str>) {
+        let addr = addr.and_then(parse_addr);
+        let count = count.and_then(|c| c.parse::<u16>().ok()).unwrap_or(1);
+        match addr {
+            Some(addr) => {
+                for offset in 0..count {
+                    let a = addr.wrapping_add(offset);
+                    let word = self.vm.mem_read(a as usize);
+                    match Opcode::try_from(word) {
+                        Ok(opcode) => println!("{a:#06x}: {opcode}"),
+                        Err(()) => println!("{a:#06x}: <invalid {word:#06x}>"),
+                    }
+                }
+            }
+            None => println!("usage: disas <addr> [count]"),
+        }
+    }
+}
+
+fn print_outcome(outcome: &StepOutcome) {
+    match &outcome.opcode {
+        Some(opcode) => println!("{:#06x}: {opcode}", outcome.pc_before),
+        None => {
+            println!("Program halted.");
+            return;
+        }
+    }
+    for &(i, before, after) in &outcome.changed_registers {
+        println!("{} {before:#06x} -> {after:#06x}", REG_NAMES[i]);
+    }
+    for &(addr, before, after) in &outcome.changed_memory {
+        println!("mem[{addr:#06x}] {before:#06x} -> {after:#06x}");
+    }
+}
+
+fn parse_addr(s: &str) -> Option<u16> {
+    let s = s.strip_prefix("0x").unwrap_or(s);
+    u16::from_str_radix(s, 16).ok()
+}
+
+fn print_help() {
+    println!(
+        "\
+step, s                execute one instruction
+continue, c             run until a breakpoint, watchpoint, or HALT
+break, b <addr>         set a breakpoint at addr (hex)
+watch, w <addr>         set a watchpoint at addr (hex)
+trace <on|off>          toggle per-instruction eprintln! tracing
+regs                    dump R0-R7/PC/PSR
+mem <addr> [count]      hex-dump memory starting at addr
+disas <addr> [count]    disassemble memory starting at addr
+help, h                 show this text
+quit, q                 exit the debugger"
+    );
+}
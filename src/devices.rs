@@ -0,0 +1,170 @@
+//! Memory-mapped I/O devices. Each device claims a handful of addresses in
+//! the LC-3 address space (traditionally 0xFE00 and up) and is consulted by
+//! `VM::read`/`VM::write` before falling back to plain memory.
+
+use crate::terminal::Console;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// A device addressable through `LDR`/`STR`-style memory access rather than
+/// a dedicated opcode. `read`/`write` return `None`/`false` for addresses
+/// the device doesn't own, so the registry can try the next one.
+pub(crate) trait MmioDevice {
+    fn read(&mut self, addr: u16) -> Option<u16>;
+    fn write(&mut self, addr: u16, val: u16) -> bool;
+}
+
+const MR_KBSR: u16 = 0xFE00; // Keyboard Status
+const MR_KBDR: u16 = 0xFE02; // Keyboard Data
+const MR_DSR: u16 = 0xFE04; // Display Status
+const MR_DDR: u16 = 0xFE06; // Display Data
+
+/// Pending key, interrupt-enable bit, and console handle shared between
+/// `VM::run`'s interrupt-raising logic and `KeyboardDevice`'s MMIO
+/// registers, the same split `TimerState`/`TimerDevice` use: raising the
+/// interrupt needs PSR/stack access the `MmioDevice` trait doesn't expose.
+pub(crate) struct KeyboardState {
+    console: Rc<RefCell<Box<dyn Console>>>,
+    pending: Option<u8>,
+    pub(crate) interrupt_enabled: bool,
+}
+
+impl KeyboardState {
+    pub(crate) fn new(console: Rc<RefCell<Box<dyn Console>>>) -> Self {
+        Self {
+            console,
+            pending: None,
+            interrupt_enabled: false,
+        }
+    }
+
+    /// Buffers a key from the console if one isn't already pending, and
+    /// reports whether one is now ready.
+    pub(crate) fn poll(&mut self) -> bool {
+        if self.pending.is_none() {
+            let mut console = self.console.borrow_mut();
+            if console.key_pressed() {
+                self.pending = console.read_char();
+            }
+        }
+        self.pending.is_some()
+    }
+}
+
+/// KBSR/KBDR: bit 15 of KBSR is set once a key is buffered (bit 14 is the
+/// interrupt-enable bit, set by writing KBSR); reading KBDR consumes it.
+pub(crate) struct KeyboardDevice {
+    state: Rc<RefCell<KeyboardState>>,
+}
+
+impl KeyboardDevice {
+    pub(crate) fn new(state: Rc<RefCell<KeyboardState>>) -> Self {
+        Self { state }
+    }
+}
+
+impl MmioDevice for KeyboardDevice {
+    fn read(&mut self, addr: u16) -> Option<u16> {
+        match addr {
+            MR_KBSR => {
+                let mut state = self.state.borrow_mut();
+                let ready = state.poll();
+                Some(((ready as u16) << 15) | ((state.interrupt_enabled as u16) << 14))
+            }
+            MR_KBDR => Some(self.state.borrow_mut().pending.take().unwrap_or(0) as u16),
+            _ => None,
+        }
+    }
+
+    fn write(&mut self, addr: u16, val: u16) -> bool {
+        match addr {
+            MR_KBSR => {
+                self.state.borrow_mut().interrupt_enabled = val & (1 << 14) != 0;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// DSR/DDR: the display is always ready, so DSR reads as "ready" and a DDR
+/// write forwards the low byte straight to the console.
+pub(crate) struct DisplayDevice {
+    console: Rc<RefCell<Box<dyn Console>>>,
+}
+
+impl DisplayDevice {
+    pub(crate) fn new(console: Rc<RefCell<Box<dyn Console>>>) -> Self {
+        Self { console }
+    }
+}
+
+impl MmioDevice for DisplayDevice {
+    fn read(&mut self, addr: u16) -> Option<u16> {
+        match addr {
+            MR_DSR => Some(1 << 15),
+            MR_DDR => Some(0),
+            _ => None,
+        }
+    }
+
+    fn write(&mut self, addr: u16, val: u16) -> bool {
+        match addr {
+            MR_DDR => {
+                self.console.borrow_mut().write_byte(val as u8);
+                true
+            }
+            MR_DSR => true,
+            _ => false,
+        }
+    }
+}
+
+const MR_TSR: u16 = 0xFE08; // Timer Status/Control: bit 15 = enabled
+const MR_TCTR: u16 = 0xFE0A; // Timer tick count, incremented by VM::run
+
+/// Tick count and enable bit shared between `VM::run`'s interrupt-raising
+/// logic and `TimerDevice`'s MMIO registers. `VM::run` owns ticking it
+/// forward since raising the interrupt needs PSR/stack access the
+/// `MmioDevice` trait doesn't expose.
+#[derive(Default)]
+pub(crate) struct TimerState {
+    pub(crate) enabled: bool,
+    pub(crate) ticks: u16,
+}
+
+/// TSR/TCTR: enabling the timer sets bit 15 of TSR; TCTR reads back the
+/// running tick count maintained by `VM::run`.
+pub(crate) struct TimerDevice {
+    state: Rc<RefCell<TimerState>>,
+}
+
+impl TimerDevice {
+    pub(crate) fn new(state: Rc<RefCell<TimerState>>) -> Self {
+        Self { state }
+    }
+}
+
+impl MmioDevice for TimerDevice {
+    fn read(&mut self, addr: u16) -> Option<u16> {
+        match addr {
+            MR_TSR => Some(if self.state.borrow().enabled { 1 << 15 } else { 0 }),
+            MR_TCTR => Some(self.state.borrow().ticks),
+            _ => None,
+        }
+    }
+
+    fn write(&mut self, addr: u16, val: u16) -> bool {
+        match addr {
+            MR_TSR => {
+                self.state.borrow_mut().enabled = val & (1 << 15) != 0;
+                true
+            }
+            MR_TCTR => {
+                self.state.borrow_mut().ticks = val;
+                true
+            }
+            _ => false,
+        }
+    }
+}
@@ -1,35 +1,132 @@
 #![allow(clippy::unusual_byte_groupings)]
 #![allow(clippy::upper_case_acronyms)]
 
-use termios::*;
-
-mod memory;
+mod asm;
+mod debugger;
+mod devices;
 mod opcodes;
+mod os_image;
+mod terminal;
 mod util;
 mod vm;
 
-use crate::vm::VM;
-use std::{env, fs};
+use crate::debugger::Debugger;
+use crate::terminal::TermConsole;
+use crate::vm::{Config, VM};
+use std::{env, fs, process};
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    let Some(filename) = args.get(1) else {
-        println!("You must provide an obj file");
-        std::process::exit(1);
-    };
+const USAGE: &str = "\
+lc3-vm [OPTIONS] <file.obj|file.asm>
+
+A `.asm` file is assembled in-memory before loading; anything else is
+loaded as a raw LC-3 object file.
+
+OPTIONS:
+    -h, --help              Print this help text and exit
+    --trace                 Log every fetched instruction and register state to stderr
+    --origin <hex>          Override the default load origin (e.g. 0x3000)
+    --max-cycles <n>        Halt the VM after executing n instructions
+    --debug                 Drop into an interactive debugger instead of free-running
+    --os <file>             Load a base OS image before the user program (defaults to the builtin one)
+    --timer-quotient <n>    Tick the timer every n instructions, raising its interrupt once enabled via TSR (default: disabled)
+";
 
-    // Terminal stuff
-    let stdin = 0;
-    let mut termios = Termios::from_fd(stdin).expect("failed to initialize terminal");
-    termios.c_iflag &= IGNBRK | BRKINT | PARMRK | ISTRIP | INLCR | IGNCR | ICRNL | IXON;
-    termios.c_lflag &= !(ICANON | ECHO); // no echo and canonical mode
-    tcsetattr(stdin, TCSANOW, &termios).expect("failed to initialize terminal");
+fn parse_args(args: &[String]) -> Result<Config, String> {
+    let mut filename = None;
+    let mut trace = false;
+    let mut origin = None;
+    let mut max_cycles = None;
+    let mut debug = false;
+    let mut os = None;
+    let mut timer_quotient = 0;
 
-    println!("Loading file {filename}");
-    let data: Vec<u8> = fs::read(filename).expect("Failed to load file");
-    let mut vm = VM::new(&data);
-    vm.run();
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "-h" | "--help" => {
+                print!("{USAGE}");
+                process::exit(0);
+            }
+            "--trace" => trace = true,
+            "--debug" => debug = true,
+            "--os" => {
+                i += 1;
+                os = Some(args.get(i).ok_or("--os requires a value")?.clone());
+            }
+            "--origin" => {
+                i += 1;
+                let val = args.get(i).ok_or("--origin requires a value")?;
+                let val = val.strip_prefix("0x").unwrap_or(val);
+                origin = Some(
+                    u16::from_str_radix(val, 16).map_err(|_| format!("invalid --origin value {val}"))?,
+                );
+            }
+            "--max-cycles" => {
+                i += 1;
+                let val = args.get(i).ok_or("--max-cycles requires a value")?;
+                max_cycles = Some(
+                    val.parse::<u64>()
+                        .map_err(|_| format!("invalid --max-cycles value {val}"))?,
+                );
+            }
+            "--timer-quotient" => {
+                i += 1;
+                let val = args.get(i).ok_or("--timer-quotient requires a value")?;
+                timer_quotient = val
+                    .parse::<u64>()
+                    .map_err(|_| format!("invalid --timer-quotient value {val}"))?;
+            }
+            other => {
+                if filename.is_some() {
+                    return Err(format!("unexpected argument {other}"));
+                }
+                filename = Some(other.to_string());
+            }
+        }
+        i += 1;
+    }
 
-    // Restore terminal to default settings
-    tcsetattr(stdin, TCSANOW, &termios).expect("failed to close terminal");
+    let filename = filename.ok_or("You must provide an obj file")?;
+    Ok(Config {
+        filename,
+        trace,
+        origin,
+        max_cycles,
+        debug,
+        os,
+        timer_quotient,
+    })
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let config = parse_args(&args).unwrap_or_else(|err| {
+        eprintln!("error: {err}");
+        print!("{USAGE}");
+        process::exit(1);
+    });
+
+    println!("Loading file {}", config.filename);
+    let data: Vec<u8> = if config.filename.ends_with(".asm") {
+        let source = fs::read_to_string(&config.filename).expect("Failed to load file");
+        asm::assemble(&source).unwrap_or_else(|err| {
+            eprintln!("assembler error: {err}");
+            process::exit(1);
+        })
+    } else {
+        fs::read(&config.filename).expect("Failed to load file")
+    };
+    let console = Box::new(TermConsole::new());
+    let vm = VM::new(&data, &config, console);
+    if config.debug {
+        Debugger::new(vm).run();
+    } else {
+        let mut vm = vm;
+        if let Err(err) = vm.run() {
+            eprintln!("VM error: {err}");
+            process::exit(1);
+        }
+    }
+    // `vm` (and the `Console` it owns) drops here, restoring cooked mode
+    // even if `vm.run()` unwound from a panic.
 }
@@ -1,13 +1,16 @@
-use crate::util::{sign_ext_imm11, sign_ext_imm5, sign_ext_imm6, sign_ext_imm9};
-use crate::vm::TrapCode;
+use crate::util::{
+    sign_ext_imm11, sign_ext_imm5, sign_ext_imm6, sign_ext_imm9, trunc_imm11, trunc_imm5,
+    trunc_imm6, trunc_imm9,
+};
+use std::fmt;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Argument {
     Reg(usize),
     Immediate(i16),
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Opcode {
     ADD {
         // Add (dr <- sr1 + sr2/imm5)
@@ -85,8 +88,10 @@ pub enum Opcode {
         offset: i16,
     },
     TRAP {
-        // Execute Trap
-        trap_code: TrapCode,
+        // Execute Trap: jump to the service routine address stored at
+        // mem[0x0000 + vector], entering supervisor mode and saving PSR/PC
+        // on the supervisor stack so the routine returns via RTI.
+        vector: u8,
     },
     RESERVED, // Unused. Throws an Illegal Opcode Exception.
 }
@@ -240,17 +245,11 @@ impl TryFrom<u16> for Opcode {
                 })
             }
             0b1111 => {
-                let trap_code_hex = instruction & 0b1111_1111;
-                let trap_code = match trap_code_hex {
-                    0x20 => TrapCode::Getc,
-                    0x21 => TrapCode::Out,
-                    0x22 => TrapCode::Puts,
-                    0x23 => TrapCode::In,
-                    0x24 => TrapCode::Putsp,
-                    0x25 => TrapCode::Halt,
-                    _ => panic!("Unknown trap code {trap_code_hex} !"),
-                };
-                Ok(Opcode::TRAP { trap_code })
+                // TRAP: the vector is a raw trapvect8, not a closed set of
+                // known codes, so any 8-bit value decodes (dispatch through
+                // the vector table is what decides whether it does anything).
+                let vector = (instruction & 0b1111_1111) as u8;
+                Ok(Opcode::TRAP { vector })
             }
             0b1101 => Ok(Opcode::RESERVED),
             _ => {
@@ -261,10 +260,116 @@ impl TryFrom<u16> for Opcode {
     }
 }
 
+impl fmt::Display for Argument {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Argument::Reg(r) => write!(f, "R{r}"),
+            Argument::Immediate(imm) => write!(f, "#{imm}"),
+        }
+    }
+}
+
+impl fmt::Display for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Opcode::ADD { dr, sr1, sr2 } => write!(f, "ADD R{dr}, R{sr1}, {sr2}"),
+            Opcode::AND { dr, sr1, sr2 } => write!(f, "AND R{dr}, R{sr1}, {sr2}"),
+            Opcode::BR { n, z, p, offset } => {
+                write!(f, "BR")?;
+                if *n {
+                    write!(f, "n")?;
+                }
+                if *z {
+                    write!(f, "z")?;
+                }
+                if *p {
+                    write!(f, "p")?;
+                }
+                write!(f, " #{offset}")
+            }
+            Opcode::JMP { base_r } => write!(f, "JMP R{base_r}"),
+            Opcode::RET => write!(f, "RET"),
+            Opcode::JSR { offset } => write!(f, "JSR #{offset}"),
+            Opcode::JSRR { base_r } => write!(f, "JSRR R{base_r}"),
+            Opcode::LD { dr, offset } => write!(f, "LD R{dr}, #{offset}"),
+            Opcode::LDI { dr, offset } => write!(f, "LDI R{dr}, #{offset}"),
+            Opcode::LDR { dr, base_r, offset } => write!(f, "LDR R{dr}, R{base_r}, #{offset}"),
+            Opcode::LEA { dr, offset } => write!(f, "LEA R{dr}, #{offset}"),
+            Opcode::NOT { dr, sr } => write!(f, "NOT R{dr}, R{sr}"),
+            Opcode::RTI => write!(f, "RTI"),
+            Opcode::ST { sr, offset } => write!(f, "ST R{sr}, #{offset}"),
+            Opcode::STI { sr, offset } => write!(f, "STI R{sr}, #{offset}"),
+            Opcode::STR { sr, base_r, offset } => write!(f, "STR R{sr}, R{base_r}, #{offset}"),
+            Opcode::TRAP { vector } => match vector {
+                0x20 => write!(f, "GETC"),
+                0x21 => write!(f, "OUT"),
+                0x22 => write!(f, "PUTS"),
+                0x23 => write!(f, "IN"),
+                0x24 => write!(f, "PUTSP"),
+                0x25 => write!(f, "HALT"),
+                _ => write!(f, "TRAP x{vector:02X}"),
+            },
+            Opcode::RESERVED => write!(f, "RESERVED"),
+        }
+    }
+}
+
+impl Opcode {
+    /// Bit-packs this opcode back into a machine word, the inverse of
+    /// `TryFrom<u16>`. Immediates are masked to their instruction-format
+    /// width, so out-of-range values are truncated rather than rejected
+    /// (matching how the decoder only ever sees an in-range field).
+    pub(crate) fn encode(&self) -> u16 {
+        match self {
+            Opcode::ADD { dr, sr1, sr2 } | Opcode::AND { dr, sr1, sr2 } => {
+                let op = if matches!(self, Opcode::ADD { .. }) {
+                    0b0001
+                } else {
+                    0b0101
+                };
+                let base = (op << 12) | ((*dr as u16) << 9) | ((*sr1 as u16) << 6);
+                match sr2 {
+                    Argument::Reg(sr2) => base | (*sr2 as u16),
+                    Argument::Immediate(imm5) => base | (1 << 5) | trunc_imm5(*imm5),
+                }
+            }
+            Opcode::BR { n, z, p, offset } => {
+                ((*n as u16) << 11) | ((*z as u16) << 10) | ((*p as u16) << 9) | trunc_imm9(*offset)
+            }
+            Opcode::JMP { base_r } => (0b1100 << 12) | ((*base_r as u16) << 6),
+            Opcode::RET => (0b1100 << 12) | (0b111 << 6),
+            Opcode::JSR { offset } => (0b0100 << 12) | (1 << 11) | trunc_imm11(*offset),
+            Opcode::JSRR { base_r } => (0b0100 << 12) | ((*base_r as u16) << 6),
+            Opcode::LD { dr, offset } => (0b0010 << 12) | ((*dr as u16) << 9) | trunc_imm9(*offset),
+            Opcode::LDI { dr, offset } => {
+                (0b1010 << 12) | ((*dr as u16) << 9) | trunc_imm9(*offset)
+            }
+            Opcode::LDR { dr, base_r, offset } => {
+                (0b0110 << 12) | ((*dr as u16) << 9) | ((*base_r as u16) << 6) | trunc_imm6(*offset)
+            }
+            Opcode::LEA { dr, offset } => {
+                (0b1110 << 12) | ((*dr as u16) << 9) | trunc_imm9(*offset)
+            }
+            Opcode::NOT { dr, sr } => {
+                (0b1001 << 12) | ((*dr as u16) << 9) | ((*sr as u16) << 6) | 0b11_1111
+            }
+            Opcode::RTI => 0b1000 << 12,
+            Opcode::ST { sr, offset } => (0b0011 << 12) | ((*sr as u16) << 9) | trunc_imm9(*offset),
+            Opcode::STI { sr, offset } => {
+                (0b1011 << 12) | ((*sr as u16) << 9) | trunc_imm9(*offset)
+            }
+            Opcode::STR { sr, base_r, offset } => {
+                (0b0111 << 12) | ((*sr as u16) << 9) | ((*base_r as u16) << 6) | trunc_imm6(*offset)
+            }
+            Opcode::TRAP { vector } => (0b1111 << 12) | (*vector as u16),
+            Opcode::RESERVED => 0b1101 << 12,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::opcodes::{Argument, Opcode};
-    use crate::vm::TrapCode;
 
     #[test]
     fn decode_add_reg() {
@@ -610,64 +715,36 @@ mod tests {
     fn decode_trap() {
         let ins = 0b1111_0000_0010_0000;
         let op = Opcode::try_from(ins).unwrap();
-        assert_eq!(
-            op,
-            Opcode::TRAP {
-                trap_code: TrapCode::Getc
-            }
-        );
+        assert_eq!(op, Opcode::TRAP { vector: 0x20 });
 
         let ins = 0b1111_0000_0010_0001;
         let op = Opcode::try_from(ins).unwrap();
-        assert_eq!(
-            op,
-            Opcode::TRAP {
-                trap_code: TrapCode::Out
-            }
-        );
+        assert_eq!(op, Opcode::TRAP { vector: 0x21 });
 
         let ins = 0b1111_0000_0010_0010;
         let op = Opcode::try_from(ins).unwrap();
-        assert_eq!(
-            op,
-            Opcode::TRAP {
-                trap_code: TrapCode::Puts
-            }
-        );
+        assert_eq!(op, Opcode::TRAP { vector: 0x22 });
 
         let ins = 0b1111_0000_0010_0011;
         let op = Opcode::try_from(ins).unwrap();
-        assert_eq!(
-            op,
-            Opcode::TRAP {
-                trap_code: TrapCode::In
-            }
-        );
+        assert_eq!(op, Opcode::TRAP { vector: 0x23 });
 
         let ins = 0b1111_0000_0010_0100;
         let op = Opcode::try_from(ins).unwrap();
-        assert_eq!(
-            op,
-            Opcode::TRAP {
-                trap_code: TrapCode::Putsp
-            }
-        );
+        assert_eq!(op, Opcode::TRAP { vector: 0x24 });
 
         let ins = 0b1111_0000_0010_0101;
         let op = Opcode::try_from(ins).unwrap();
-        assert_eq!(
-            op,
-            Opcode::TRAP {
-                trap_code: TrapCode::Halt
-            }
-        );
+        assert_eq!(op, Opcode::TRAP { vector: 0x25 });
     }
 
     #[test]
-    #[should_panic]
-    fn decode_trap_invalid() {
+    fn decode_trap_arbitrary_vector() {
+        // Any 8-bit vector decodes; dispatch through the vector table is
+        // what decides whether anything is installed there.
         let ins = 0b1111_0000_0010_0111;
-        Opcode::try_from(ins).unwrap();
+        let op = Opcode::try_from(ins).unwrap();
+        assert_eq!(op, Opcode::TRAP { vector: 0x27 });
     }
 
     #[test]
@@ -676,4 +753,56 @@ mod tests {
         let op = Opcode::try_from(ins).unwrap();
         assert_eq!(op, Opcode::RESERVED);
     }
+
+    #[test]
+    fn encode_roundtrips_every_decodable_word() {
+        for ins in 0..=u16::MAX {
+            if let Ok(op) = Opcode::try_from(ins) {
+                assert_eq!(Opcode::try_from(op.encode()), Ok(op));
+            }
+        }
+    }
+
+    #[test]
+    fn display_formats_canonical_assembly() {
+        assert_eq!(
+            Opcode::ADD {
+                dr: 2,
+                sr1: 3,
+                sr2: Argument::Reg(1)
+            }
+            .to_string(),
+            "ADD R2, R3, R1"
+        );
+        assert_eq!(
+            Opcode::ADD {
+                dr: 0,
+                sr1: 4,
+                sr2: Argument::Immediate(9)
+            }
+            .to_string(),
+            "ADD R0, R4, #9"
+        );
+        assert_eq!(
+            Opcode::BR {
+                n: true,
+                z: false,
+                p: true,
+                offset: 24
+            }
+            .to_string(),
+            "BRnp #24"
+        );
+        assert_eq!(
+            Opcode::LDR {
+                dr: 5,
+                base_r: 1,
+                offset: 7
+            }
+            .to_string(),
+            "LDR R5, R1, #7"
+        );
+        assert_eq!(Opcode::TRAP { vector: 0x25 }.to_string(), "HALT");
+        assert_eq!(Opcode::TRAP { vector: 0x27 }.to_string(), "TRAP x27");
+    }
 }
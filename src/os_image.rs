@@ -0,0 +1,335 @@
+//! A minimal built-in LC-3 OS image: the trap vector table at low memory
+//! (`mem[0x0000 + trap_vector]`) pointing at real LC-3 service routines
+//! assembled into the conventional 0xF000 OS region, loaded ahead of the
+//! user program so `.obj` files that `TRAP` into GETC/OUT/PUTS/IN/PUTSP/HALT
+//! find authentic handlers there instead of whatever garbage happens to be
+//! in that memory. Because dispatch goes through the vector table rather
+//! than a Rust-native switch, a program can install its own handler by
+//! overwriting the vector.
+//!
+//! The routines below are assembled from a tiny symbolic instruction list
+//! (see `Item`) rather than hand-counted offsets, so labels can be
+//! rearranged without re-deriving PC-relative offsets by hand.
+
+use std::collections::HashMap;
+
+const R0: u16 = 0;
+const R1: u16 = 1;
+const R2: u16 = 2;
+const R3: u16 = 3;
+const R7: u16 = 7;
+
+/// Base address the routines assembled below are installed at.
+const ROUTINE_BASE: u16 = 0xF000;
+
+/// The Machine Control Register: bit 15 clear halts the VM (checked by
+/// `VM::write`). The HALT routine clears it via `STI` rather than any
+/// VM-specific API, just like real LC-3 hardware.
+const MCR: u16 = 0xFFFE;
+
+/// A symbolic LC-3 instruction or data item. `Builder::assemble` resolves
+/// label references into PC-relative offsets so routines read close to the
+/// assembly they represent.
+enum Item {
+    Label(&'static str),
+    Fill(u16),
+    Stringz(&'static str),
+    AddImm { dr: u16, sr1: u16, imm5: i16 },
+    And { dr: u16, sr1: u16, sr2: u16 },
+    AndImm { dr: u16, sr1: u16, imm5: i16 },
+    Br { n: bool, z: bool, p: bool, label: &'static str },
+    Rti,
+    Ld { dr: u16, label: &'static str },
+    Ldi { dr: u16, label: &'static str },
+    Ldr { dr: u16, base_r: u16, offset6: i16 },
+    Lea { dr: u16, label: &'static str },
+    St { sr: u16, label: &'static str },
+    Sti { sr: u16, label: &'static str },
+    Trap { vector: u16 },
+}
+
+/// `RTI`: trap routines return through it rather than `RET` now that `TRAP`
+/// enters supervisor mode via the same PSR/PC stack push `enter_vectored`
+/// uses for interrupts, instead of stashing the return address in R7.
+const RTI: u16 = 0b1000 << 12;
+
+/// Assembles `items` starting at `base`, returning `(address, word)` pairs
+/// plus the resolved address of every `Item::Label`.
+fn assemble(base: u16, items: &[Item]) -> (Vec<(u16, u16)>, HashMap<&'static str, u16>) {
+    let mut labels = HashMap::new();
+    let mut addr = base;
+    for item in items {
+        match item {
+            Item::Label(name) => {
+                labels.insert(*name, addr);
+            }
+            Item::Stringz(s) => addr = addr.wrapping_add(s.len() as u16 + 1),
+            _ => addr = addr.wrapping_add(1),
+        }
+    }
+
+    let mut words = Vec::new();
+    let mut addr = base;
+    for item in items {
+        match item {
+            Item::Label(_) => {}
+            Item::Stringz(s) => {
+                for byte in s.bytes() {
+                    words.push((addr, byte as u16));
+                    addr = addr.wrapping_add(1);
+                }
+                words.push((addr, 0));
+                addr = addr.wrapping_add(1);
+            }
+            Item::Fill(word) => {
+                words.push((addr, *word));
+                addr = addr.wrapping_add(1);
+            }
+            other => {
+                words.push((addr, encode(other, addr, &labels)));
+                addr = addr.wrapping_add(1);
+            }
+        }
+    }
+    (words, labels)
+}
+
+/// PC-relative offset from the instruction at `addr` (PC is `addr + 1` once
+/// fetched) to `label`, truncated to `bits` two's-complement bits.
+fn pc_offset(addr: u16, label: &'static str, labels: &HashMap<&'static str, u16>, bits: u16) -> u16 {
+    let target = *labels.get(label).unwrap_or_else(|| panic!("unknown label {label}"));
+    let rel = target.wrapping_sub(addr.wrapping_add(1));
+    rel & ((1 << bits) - 1)
+}
+
+fn encode(item: &Item, addr: u16, labels: &HashMap<&'static str, u16>) -> u16 {
+    match item {
+        Item::AddImm { dr, sr1, imm5 } => (0b0001 << 12) | (dr << 9) | (sr1 << 6) | (1 << 5) | (*imm5 as u16 & 0x1F),
+        Item::And { dr, sr1, sr2 } => (0b0101 << 12) | (dr << 9) | (sr1 << 6) | sr2,
+        Item::AndImm { dr, sr1, imm5 } => (0b0101 << 12) | (dr << 9) | (sr1 << 6) | (1 << 5) | (*imm5 as u16 & 0x1F),
+        Item::Br { n, z, p, label } => {
+            let offset = pc_offset(addr, label, labels, 9);
+            ((*n as u16) << 11) | ((*z as u16) << 10) | ((*p as u16) << 9) | offset
+        }
+        Item::Rti => RTI,
+        Item::Ld { dr, label } => (0b0010 << 12) | (dr << 9) | pc_offset(addr, label, labels, 9),
+        Item::Ldi { dr, label } => (0b1010 << 12) | (dr << 9) | pc_offset(addr, label, labels, 9),
+        Item::Ldr { dr, base_r, offset6 } => (0b0110 << 12) | (dr << 9) | (base_r << 6) | (*offset6 as u16 & 0x3F),
+        Item::Lea { dr, label } => (0b1110 << 12) | (dr << 9) | pc_offset(addr, label, labels, 9),
+        Item::St { sr, label } => (0b0011 << 12) | (sr << 9) | pc_offset(addr, label, labels, 9),
+        Item::Sti { sr, label } => (0b1011 << 12) | (sr << 9) | pc_offset(addr, label, labels, 9),
+        Item::Trap { vector } => (0b1111 << 12) | (vector & 0xFF),
+        Item::Label(_) | Item::Fill(_) | Item::Stringz(_) => unreachable!("handled before encode"),
+    }
+}
+
+/// `GETC` (x20): block until a key is buffered, return it in R0.
+fn getc() -> Vec<Item> {
+    vec![
+        Item::Label("GETC"),
+        Item::St { sr: R1, label: "GETC_R1" },
+        Item::Label("GETC_WAIT"),
+        Item::Ldi { dr: R1, label: "GETC_KBSR" },
+        Item::Br { n: false, z: true, p: true, label: "GETC_WAIT" },
+        Item::Ldi { dr: R0, label: "GETC_KBDR" },
+        Item::Ld { dr: R1, label: "GETC_R1" },
+        Item::Rti,
+        Item::Label("GETC_R1"),
+        Item::Fill(0),
+        Item::Label("GETC_KBSR"),
+        Item::Fill(0xFE00),
+        Item::Label("GETC_KBDR"),
+        Item::Fill(0xFE02),
+    ]
+}
+
+/// `OUT` (x21): write the character in R0 to the display.
+fn out() -> Vec<Item> {
+    vec![
+        Item::Label("OUT"),
+        Item::St { sr: R1, label: "OUT_R1" },
+        Item::Label("OUT_WAIT"),
+        Item::Ldi { dr: R1, label: "OUT_DSR" },
+        Item::Br { n: false, z: true, p: true, label: "OUT_WAIT" },
+        Item::Sti { sr: R0, label: "OUT_DDR" },
+        Item::Ld { dr: R1, label: "OUT_R1" },
+        Item::Rti,
+        Item::Label("OUT_R1"),
+        Item::Fill(0),
+        Item::Label("OUT_DSR"),
+        Item::Fill(0xFE04),
+        Item::Label("OUT_DDR"),
+        Item::Fill(0xFE06),
+    ]
+}
+
+/// `PUTS` (x22): write the null-terminated string (one char per word)
+/// pointed to by R0.
+fn puts() -> Vec<Item> {
+    vec![
+        Item::Label("PUTS"),
+        Item::St { sr: R0, label: "PUTS_R0" },
+        Item::St { sr: R1, label: "PUTS_R1" },
+        Item::St { sr: R2, label: "PUTS_R2" },
+        Item::Label("PUTS_LOOP"),
+        Item::Ldr { dr: R1, base_r: R0, offset6: 0 },
+        Item::Br { n: false, z: true, p: false, label: "PUTS_DONE" },
+        Item::Label("PUTS_WAIT"),
+        Item::Ldi { dr: R2, label: "PUTS_DSR" },
+        Item::Br { n: false, z: true, p: true, label: "PUTS_WAIT" },
+        Item::Sti { sr: R1, label: "PUTS_DDR" },
+        Item::AddImm { dr: R0, sr1: R0, imm5: 1 },
+        Item::Br { n: true, z: true, p: true, label: "PUTS_LOOP" },
+        Item::Label("PUTS_DONE"),
+        Item::Ld { dr: R0, label: "PUTS_R0" },
+        Item::Ld { dr: R1, label: "PUTS_R1" },
+        Item::Ld { dr: R2, label: "PUTS_R2" },
+        Item::Rti,
+        Item::Label("PUTS_R0"),
+        Item::Fill(0),
+        Item::Label("PUTS_R1"),
+        Item::Fill(0),
+        Item::Label("PUTS_R2"),
+        Item::Fill(0),
+        Item::Label("PUTS_DSR"),
+        Item::Fill(0xFE04),
+        Item::Label("PUTS_DDR"),
+        Item::Fill(0xFE06),
+    ]
+}
+
+/// `IN` (x23): prompt, read one character via `GETC`, and echo it via `OUT`.
+/// The nested `TRAP`s no longer need to save/restore R7 around them: each
+/// one now enters/leaves through the supervisor stack, not R7.
+fn r#in() -> Vec<Item> {
+    vec![
+        Item::Label("IN"),
+        Item::Lea { dr: R0, label: "IN_PROMPT" },
+        Item::Trap { vector: 0x22 },
+        Item::Trap { vector: 0x20 },
+        Item::Trap { vector: 0x21 },
+        Item::Rti,
+        Item::Label("IN_PROMPT"),
+        Item::Stringz("Enter a character: "),
+    ]
+}
+
+/// `PUTSP` (x24): write the packed string (two chars per word, low byte
+/// first) pointed to by R0, via `OUT`. The high byte is extracted without a
+/// shift instruction (the base LC-3 ISA has none) by repeatedly subtracting
+/// 256 while counting how many times it fit.
+fn putsp() -> Vec<Item> {
+    vec![
+        Item::Label("PUTSP"),
+        Item::St { sr: R0, label: "PUTSP_R0" },
+        Item::St { sr: R1, label: "PUTSP_R1" },
+        Item::St { sr: R2, label: "PUTSP_R2" },
+        Item::St { sr: R3, label: "PUTSP_R3" },
+        Item::St { sr: R7, label: "PUTSP_R7" },
+        Item::AddImm { dr: R3, sr1: R0, imm5: 0 }, // R3 = string pointer (R0 is needed for OUT's char arg)
+        Item::Label("PUTSP_LOOP"),
+        Item::Ldr { dr: R1, base_r: R3, offset6: 0 },
+        Item::Br { n: false, z: true, p: false, label: "PUTSP_DONE" },
+        Item::Ld { dr: R2, label: "PUTSP_MASK_LOW" },
+        Item::And { dr: R0, sr1: R1, sr2: R2 },
+        Item::Trap { vector: 0x21 }, // OUT the low byte
+        Item::Ld { dr: R2, label: "PUTSP_MASK_HIGH" },
+        Item::And { dr: R2, sr1: R1, sr2: R2 },
+        Item::AndImm { dr: R0, sr1: R0, imm5: 0 }, // R0 = 0, reused as the high-byte accumulator
+        Item::Label("PUTSP_HIGH_LOOP"),
+        Item::AddImm { dr: R2, sr1: R2, imm5: 0 }, // re-set flags from R2's current value
+        Item::Br { n: false, z: true, p: false, label: "PUTSP_HIGH_DONE" },
+        Item::AddImm { dr: R2, sr1: R2, imm5: -16 },
+        Item::AddImm { dr: R2, sr1: R2, imm5: -16 },
+        Item::AddImm { dr: R2, sr1: R2, imm5: -16 },
+        Item::AddImm { dr: R2, sr1: R2, imm5: -16 },
+        Item::AddImm { dr: R2, sr1: R2, imm5: -16 },
+        Item::AddImm { dr: R2, sr1: R2, imm5: -16 },
+        Item::AddImm { dr: R2, sr1: R2, imm5: -16 },
+        Item::AddImm { dr: R2, sr1: R2, imm5: -16 },
+        Item::AddImm { dr: R2, sr1: R2, imm5: -16 },
+        Item::AddImm { dr: R2, sr1: R2, imm5: -16 },
+        Item::AddImm { dr: R2, sr1: R2, imm5: -16 },
+        Item::AddImm { dr: R2, sr1: R2, imm5: -16 },
+        Item::AddImm { dr: R2, sr1: R2, imm5: -16 },
+        Item::AddImm { dr: R2, sr1: R2, imm5: -16 },
+        Item::AddImm { dr: R2, sr1: R2, imm5: -16 },
+        Item::AddImm { dr: R2, sr1: R2, imm5: -16 }, // 16x -16 = -256
+        Item::AddImm { dr: R0, sr1: R0, imm5: 1 },
+        Item::Br { n: true, z: true, p: true, label: "PUTSP_HIGH_LOOP" },
+        Item::Label("PUTSP_HIGH_DONE"),
+        Item::AddImm { dr: R0, sr1: R0, imm5: 0 }, // set flags from the computed high byte
+        Item::Br { n: false, z: true, p: false, label: "PUTSP_SKIP_HIGH" },
+        Item::Trap { vector: 0x21 }, // OUT the high byte, unless it's the packed terminator
+        Item::Label("PUTSP_SKIP_HIGH"),
+        Item::AddImm { dr: R3, sr1: R3, imm5: 1 },
+        Item::Br { n: true, z: true, p: true, label: "PUTSP_LOOP" },
+        Item::Label("PUTSP_DONE"),
+        Item::Ld { dr: R0, label: "PUTSP_R0" },
+        Item::Ld { dr: R1, label: "PUTSP_R1" },
+        Item::Ld { dr: R2, label: "PUTSP_R2" },
+        Item::Ld { dr: R3, label: "PUTSP_R3" },
+        Item::Ld { dr: R7, label: "PUTSP_R7" },
+        Item::Rti,
+        Item::Label("PUTSP_R0"),
+        Item::Fill(0),
+        Item::Label("PUTSP_R1"),
+        Item::Fill(0),
+        Item::Label("PUTSP_R2"),
+        Item::Fill(0),
+        Item::Label("PUTSP_R3"),
+        Item::Fill(0),
+        Item::Label("PUTSP_R7"),
+        Item::Fill(0),
+        Item::Label("PUTSP_MASK_LOW"),
+        Item::Fill(0x00FF),
+        Item::Label("PUTSP_MASK_HIGH"),
+        Item::Fill(0xFF00),
+    ]
+}
+
+/// `HALT` (x25): print the shutdown banner, then clear the MCR's run bit.
+/// Real hardware stops fetching once that bit clears; `VM::write` mirrors
+/// that by watching writes to `MCR` rather than the routine calling back
+/// into the VM directly.
+fn halt() -> Vec<Item> {
+    vec![
+        Item::Label("HALT"),
+        Item::Lea { dr: R0, label: "HALT_MSG" },
+        Item::Trap { vector: 0x22 },
+        Item::Ld { dr: R0, label: "HALT_CLEAR" },
+        Item::Sti { sr: R0, label: "HALT_MCR_PTR" },
+        Item::Label("HALT_MSG"),
+        Item::Stringz("\n\nHalting the LC-3...\n\n"),
+        Item::Label("HALT_CLEAR"),
+        Item::Fill(0x0000),
+        Item::Label("HALT_MCR_PTR"),
+        Item::Fill(MCR),
+    ]
+}
+
+/// `(address, word)` pairs to poke into memory ahead of the user program:
+/// the trap vector table at `0x0020..0x0026` plus the routines it points
+/// to, assembled into the 0xF000 OS region.
+pub(crate) fn words() -> Vec<(u16, u16)> {
+    let mut items = Vec::new();
+    items.extend(getc());
+    items.extend(out());
+    items.extend(puts());
+    items.extend(r#in());
+    items.extend(putsp());
+    items.extend(halt());
+
+    let (mut image, labels) = assemble(ROUTINE_BASE, &items);
+    for (vector, label) in [
+        (0x20u16, "GETC"),
+        (0x21, "OUT"),
+        (0x22, "PUTS"),
+        (0x23, "IN"),
+        (0x24, "PUTSP"),
+        (0x25, "HALT"),
+    ] {
+        image.push((vector, labels[label]));
+    }
+    image
+}
@@ -1,30 +1,84 @@
-use std::io;
-use std::io::{StdinLock, StdoutLock, Write};
-use termion::input::{Keys, TermRead};
+//! Cross-platform terminal handling for the VM's keyboard-status MMIO path.
+//!
+//! Previously `main` called into `termios`/`tcsetattr` directly to enable
+//! raw mode, which only compiles on Unix and leaves the terminal in raw
+//! mode if `vm.run()` panics. `TermConsole` wraps the `console` crate
+//! (which already knows how to toggle raw mode on both Unix and Windows)
+//! behind a `Drop` guard so cooked mode is always restored.
 
-pub struct Terminal<'a> {
-    stdout: StdoutLock<'a>,
-    pub stdin: Keys<StdinLock<'a>>,
+use console::Term;
+use std::io::Write;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+/// Keyboard/display access needed by the LC-3 KBSR/KBDR MMIO path.
+pub trait Console {
+    /// Non-blocking check for a pending key press.
+    fn key_pressed(&mut self) -> bool;
+    /// Consumes one buffered key press, if any.
+    fn read_char(&mut self) -> Option<u8>;
+    /// Writes one byte to the display.
+    fn write_byte(&mut self, byte: u8);
+}
+
+/// `console`-backed `Console`. A background thread drains stdin so
+/// `key_pressed`/`read_char` can poll without blocking the fetch loop.
+pub struct TermConsole {
+    out: Term,
+    pending: Option<u8>,
+    rx: Receiver<u8>,
 }
 
-impl Terminal<'_> {
-    pub(crate) fn new() -> Self {
-        let stdout = io::stdout();
-        let mut stdout = stdout.lock();
-        let stdin = io::stdin();
-        let stdin = stdin.lock();
-        let keys = stdin.keys();
-        Self { stdout, stdin: keys }
+impl TermConsole {
+    pub fn new() -> Self {
+        let (tx, rx) = mpsc::channel();
+        let stdin = Term::stdout();
+        thread::spawn(move || {
+            while let Ok(ch) = stdin.read_char() {
+                if tx.send(ch as u8).is_err() {
+                    break;
+                }
+            }
+        });
+        Self {
+            out: Term::stdout(),
+            pending: None,
+            rx,
+        }
     }
-    pub(crate) fn clear(&mut self) {
-        write!(self.stdout, "{}", termion::clear::All).unwrap();
+}
+
+impl Default for TermConsole {
+    fn default() -> Self {
+        Self::new()
     }
+}
 
-    pub(crate) fn out(&mut self, c: u8) {
-        self.stdout.write(&[c]).unwrap();
+impl Console for TermConsole {
+    fn key_pressed(&mut self) -> bool {
+        if self.pending.is_none() {
+            self.pending = self.rx.try_recv().ok();
+        }
+        self.pending.is_some()
     }
 
-    pub(crate) fn flush(&mut self) {
-        self.stdout.flush().unwrap()
+    fn read_char(&mut self) -> Option<u8> {
+        self.pending.take().or_else(|| self.rx.try_recv().ok())
+    }
+
+    fn write_byte(&mut self, byte: u8) {
+        self.out
+            .write_all(&[byte])
+            .expect("failed to write to terminal");
+        self.out.flush().expect("failed to flush terminal");
+    }
+}
+
+impl Drop for TermConsole {
+    // `console::Term` restores cooked mode on its own once dropped, but
+    // this is the single place responsible for tearing the terminal down,
+    // so a panic mid-trap can never leave the user's shell echo-less.
+    fn drop(&mut self) {
+        let _ = self.out.show_cursor();
     }
 }
@@ -39,3 +39,19 @@ pub(crate) fn sign_ext_imm11(instruction: u16) -> i16 {
         offset & 0b111_1111_1111
     }
 }
+
+pub(crate) fn trunc_imm5(imm: i16) -> u16 {
+    (imm as u16) & 0b0001_1111
+}
+
+pub(crate) fn trunc_imm6(offset: i16) -> u16 {
+    (offset as u16) & 0b11_1111
+}
+
+pub(crate) fn trunc_imm9(offset: i16) -> u16 {
+    (offset as u16) & 0b1_1111_1111
+}
+
+pub(crate) fn trunc_imm11(offset: i16) -> u16 {
+    (offset as u16) & 0b111_1111_1111
+}
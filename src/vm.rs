@@ -1,21 +1,56 @@
-use crate::opcodes::{Argument, Opcode, TrapCode};
+use crate::devices::{
+    DisplayDevice, KeyboardDevice, KeyboardState, MmioDevice, TimerDevice, TimerState,
+};
+use crate::opcodes::{Argument, Opcode};
+use crate::terminal::Console;
 use crate::util::join_u8;
+use std::cell::RefCell;
 use std::cmp::Ordering;
+use std::collections::HashSet;
 use std::fmt::{Debug, Formatter};
-use std::io;
-use std::io::{Read, Write};
+use std::rc::Rc;
 use thiserror::Error;
 
 const MEMORY_SIZE: usize = 2_usize.pow(16);
 const REG_IDX_PC: usize = 8;
-const REG_IDX_COND: usize = 9;
-const PC_START_POS: usize = 0x3000;
 const REG_RET: usize = 7;
-
-const MR_KBSR: usize = 0xFE00; // Keyboard Status memory mapping
-const MR_KBDR: usize = 0xFE02; // Keyboard Data memory mapping
-
-#[derive(PartialEq, Eq)]
+const REG_SP: usize = 6;
+
+/// PSR[15]: 0 = supervisor mode, 1 = user mode.
+const PSR_PRIVILEGE_BIT: u16 = 1 << 15;
+
+/// Base of the exception/interrupt vector table; the service routine
+/// address for vector `v` lives at `mem[EXCEPTION_VECTOR_TABLE_BASE + v]`.
+const EXCEPTION_VECTOR_TABLE_BASE: u16 = 0x0100;
+/// Vector raised by `RTI` executed in user mode.
+const PRIVILEGE_MODE_VIOLATION_VECTOR: u16 = 0x00;
+/// Vector raised by the timer interrupt every `timer_quotient` instructions.
+const TIMER_INTERRUPT_VECTOR: u16 = 0x01;
+/// Priority of the timer interrupt; only taken if it outranks the PSR's
+/// current priority field [10:8]. Taking it leaves that field unchanged.
+const TIMER_INTERRUPT_PRIORITY: u16 = 1;
+/// Vector raised when a key is buffered and KBSR's interrupt-enable bit is
+/// set, matching real LC-3's conventional keyboard interrupt vector.
+const KEYBOARD_INTERRUPT_VECTOR: u16 = 0x80;
+/// Priority of the keyboard interrupt; outranks the timer, so a keystroke
+/// is still taken while the PSR's priority masks the timer interrupt.
+const KEYBOARD_INTERRUPT_PRIORITY: u16 = 4;
+const PSR_PRIORITY_SHIFT: u16 = 8;
+const PSR_PRIORITY_MASK: u16 = 0x7;
+
+/// Initial R6 value in supervisor mode, and the value `saved_ssp` starts
+/// at before any mode switch has happened.
+const SUPERVISOR_STACK_INIT: u16 = 0x3000;
+const USER_STACK_INIT: u16 = 0xFE00;
+
+/// Machine Control Register: bit 15 clear halts the VM. `write` watches for
+/// it so the built-in `HALT` trap routine can stop the machine with a plain
+/// `STI`, the same way real LC-3 hardware does, instead of the VM exposing
+/// a Rust-level "stop running" hook.
+const MCR: u16 = 0xFFFE;
+const MCR_RUN_BIT: u16 = 1 << 15;
+
+#[derive(Debug, PartialEq, Eq)]
 enum ConditionFlag {
     Pos,
     Neg,
@@ -23,6 +58,32 @@ enum ConditionFlag {
     None,
 }
 
+/// The result of a single `VM::step`: the opcode that ran (`None` if the
+/// VM halted or the fetched word didn't decode), PC before/after, the
+/// registers whose value changed, and the backing-memory cell a store
+/// (`ST`/`STI`/`STR`) wrote to; the PSR/PC pushes made on entry to a trap
+/// or interrupt are not listed. A front end
+/// (the `--debug` REPL) uses this to print a trace without the VM itself
+/// deciding what to log.
+#[derive(Debug, Clone)]
+pub struct StepOutcome {
+    pub opcode: Option<Opcode>,
+    pub pc_before: u16,
+    pub pc_after: u16,
+    pub changed_registers: Vec<(usize, u16, u16)>,
+    pub changed_memory: Vec<(u16, u16, u16)>,
+}
+
+fn changed_registers(before: &[u16; 9], after: &[u16; 9]) -> Vec<(usize, u16, u16)> {
+    before
+        .iter()
+        .zip(after.iter())
+        .enumerate()
+        .filter(|(_, (b, a))| b != a)
+        .map(|(i, (b, a))| (i, *b, *a))
+        .collect()
+}
+
 #[derive(Error, Debug)]
 pub enum VMError {
     #[error("Register Index {0} out of bounds")]
@@ -33,72 +94,349 @@ pub enum VMError {
     MemReadOutOfBounds(usize),
     #[error("Out of bounds fetch {0:#x}")]
     FetchOutOfBounds(usize),
-    #[error("IO Error failed to flush")]
-    FlushFailed,
+    #[error("Reserved instruction executed")]
+    ReservedInstruction,
+}
+
+/// Knobs collected from the CLI that control how a run behaves, as opposed
+/// to what it does (that's `VM`'s job).
+pub struct Config {
+    pub filename: String,
+    pub trace: bool,
+    pub origin: Option<u16>,
+    pub max_cycles: Option<u64>,
+    pub debug: bool,
+    pub os: Option<String>,
+    /// Instructions per timer tick; 0 disables the timer interrupt.
+    pub timer_quotient: u64,
 }
 
 pub struct VM {
     running: bool,
-    registers: [u16; 10],
+    registers: [u16; 9],
     memory: [u16; MEMORY_SIZE],
+    trace: bool,
+    max_cycles: Option<u64>,
+    devices: Vec<Box<dyn MmioDevice>>,
+    timer: Rc<RefCell<TimerState>>,
+    timer_quotient: u64,
+    keyboard: Rc<RefCell<KeyboardState>>,
+    instruction_count: u64,
+    breakpoints: HashSet<u16>,
+    watchpoints: HashSet<u16>,
+    /// Set by `write` when it lands on the backing memory array (not a
+    /// device register), consumed by `step` to populate `StepOutcome`.
+    last_mem_write: Option<(u16, u16, u16)>,
+    /// Processor Status Register: privilege bit [15], priority [10:8],
+    /// condition codes [2:0].
+    psr: u16,
+    /// R6 while the other privilege mode is active (swapped in/out of R6
+    /// on mode transitions).
+    saved_ssp: u16,
+    saved_usp: u16,
 }
 
 impl VM {
-
-    pub(crate) fn new(data: &[u8]) -> Self {
+    pub(crate) fn new(data: &[u8], config: &Config, console: Box<dyn Console>) -> Self {
+        let console = Rc::new(RefCell::new(console));
+        let timer = Rc::new(RefCell::new(TimerState::default()));
+        let keyboard = Rc::new(RefCell::new(KeyboardState::new(Rc::clone(&console))));
+        let devices: Vec<Box<dyn MmioDevice>> = vec![
+            Box::new(KeyboardDevice::new(Rc::clone(&keyboard))),
+            Box::new(DisplayDevice::new(Rc::clone(&console))),
+            Box::new(TimerDevice::new(Rc::clone(&timer))),
+        ];
         let mut vm = VM {
-            running: false,
-            registers: [0; 10],
+            running: true,
+            registers: [0; 9],
             memory: [0; MEMORY_SIZE],
+            trace: config.trace,
+            max_cycles: config.max_cycles,
+            devices,
+            timer,
+            timer_quotient: config.timer_quotient,
+            keyboard,
+            instruction_count: 0,
+            breakpoints: HashSet::new(),
+            watchpoints: HashSet::new(),
+            last_mem_write: None,
+            psr: 0, // supervisor mode, priority 0, no condition codes set
+            saved_ssp: SUPERVISOR_STACK_INIT,
+            saved_usp: USER_STACK_INIT,
         };
-        vm.read_data_into_memory(data);
-        vm.set_pc(PC_START_POS);
+        vm.load_os_image(config.os.as_deref());
+        vm.memory[MCR as usize] = MCR_RUN_BIT;
+        vm.reg_set(REG_SP, SUPERVISOR_STACK_INIT)
+            .expect("REG_SP is a valid register index");
+        let origin = vm.read_data_into_memory(data, config.origin);
+        vm.set_pc(origin);
         vm.set_cond_flag(ConditionFlag::None);
         vm
     }
 
-    pub(crate) fn run(&mut self) {
+    fn is_user_mode(&self) -> bool {
+        self.psr & PSR_PRIVILEGE_BIT != 0
+    }
+
+    fn psr_priority(&self) -> u16 {
+        (self.psr >> PSR_PRIORITY_SHIFT) & PSR_PRIORITY_MASK
+    }
+
+    /// Advances the instruction counter and, every `timer_quotient`
+    /// instructions, ticks the timer and raises its interrupt if the
+    /// device is enabled and its priority outranks the current PSR.
+    fn tick_timer(&mut self) -> Result<(), VMError> {
+        if self.timer_quotient == 0 {
+            return Ok(());
+        }
+        self.instruction_count += 1;
+        if !self.instruction_count.is_multiple_of(self.timer_quotient) {
+            return Ok(());
+        }
+        let enabled = {
+            let mut timer = self.timer.borrow_mut();
+            timer.ticks = timer.ticks.wrapping_add(1);
+            timer.enabled
+        };
+        if enabled && TIMER_INTERRUPT_PRIORITY > self.psr_priority() {
+            self.enter_vectored(EXCEPTION_VECTOR_TABLE_BASE, TIMER_INTERRUPT_VECTOR)?;
+        }
+        Ok(())
+    }
+
+    /// Checked every cycle (unlike the timer, a keystroke isn't on a
+    /// quotient): raises the keyboard interrupt if a key is buffered, KBSR's
+    /// interrupt-enable bit is set, and its priority outranks the current
+    /// PSR.
+    fn tick_keyboard(&mut self) -> Result<(), VMError> {
+        let (ready, enabled) = {
+            let mut keyboard = self.keyboard.borrow_mut();
+            (keyboard.poll(), keyboard.interrupt_enabled)
+        };
+        if ready && enabled && KEYBOARD_INTERRUPT_PRIORITY > self.psr_priority() {
+            self.enter_vectored(EXCEPTION_VECTOR_TABLE_BASE, KEYBOARD_INTERRUPT_VECTOR)?;
+        }
+        Ok(())
+    }
+
+    fn push_supervisor_stack(&mut self, val: u16) -> Result<(), VMError> {
+        let sp = self.reg(REG_SP)?.wrapping_sub(1);
+        self.reg_set(REG_SP, sp)?;
+        self.memory[sp as usize] = val;
+        Ok(())
+    }
+
+    fn pop_supervisor_stack(&mut self) -> Result<u16, VMError> {
+        let sp = self.reg(REG_SP)?;
+        let val = self.memory[sp as usize];
+        self.reg_set(REG_SP, sp.wrapping_add(1))?;
+        Ok(val)
+    }
+
+    /// Enters supervisor mode for an exception/interrupt/trap: swaps in
+    /// the supervisor stack if coming from user mode, pushes the old PSR
+    /// and PC, clears the privilege bit, then jumps through `table_base +
+    /// vector`.
+    fn enter_vectored(&mut self, table_base: u16, vector: u16) -> Result<(), VMError> {
+        if self.is_user_mode() {
+            self.saved_usp = self.reg(REG_SP)?;
+            self.reg_set(REG_SP, self.saved_ssp)?;
+        }
+        let old_psr = self.psr;
+        let old_pc = self.pc() as u16;
+        self.push_supervisor_stack(old_psr)?;
+        self.push_supervisor_stack(old_pc)?;
+        self.psr &= !PSR_PRIVILEGE_BIT;
+        let entry = self.memory[(table_base.wrapping_add(vector)) as usize];
+        self.set_pc(entry as usize);
+        Ok(())
+    }
+
+    /// Loads the OS/trap-routine ROM into low memory before the user
+    /// program, either from `--os <file>` or this crate's built-in image.
+    fn load_os_image(&mut self, os_path: Option<&str>) {
+        match os_path {
+            Some(path) => {
+                let bytes = std::fs::read(path)
+                    .unwrap_or_else(|err| panic!("failed to read --os file {path}: {err}"));
+                self.read_data_into_memory(&bytes, None);
+            }
+            None => {
+                for (addr, word) in crate::os_image::words() {
+                    self.memory[addr as usize] = word;
+                }
+            }
+        }
+    }
+
+    /// Runs headlessly until halted or `--max-cycles` is reached, ignoring
+    /// breakpoints/watchpoints (there's no front end to report them to).
+    pub(crate) fn run(&mut self) -> Result<(), VMError> {
         self.running = true;
+        let mut cycles: u64 = 0;
 
         while self.running {
-            // Fetch
-            let instruction = self.fetch();
-            self.advance_pc();
+            self.tick_timer()?;
+            self.tick_keyboard()?;
 
-            // Decode
-            let Ok(opcode) = Opcode::try_from(instruction) else {
-                println!("ERR: {instruction} not recognized!");
-                self.running = false;
+            if let Some(max_cycles) = self.max_cycles {
+                if cycles >= max_cycles {
+                    eprintln!("Halting: reached --max-cycles limit of {max_cycles}");
+                    break;
+                }
+            }
+            cycles += 1;
+
+            if self.step()?.opcode.is_none() {
                 break;
-            };
+            }
+        }
+        Ok(())
+    }
+
+    /// Loops `step` until a breakpoint/watchpoint fires or the program
+    /// halts, returning the `StepOutcome` of the last instruction executed.
+    /// Used by the `--debug` REPL's `continue` command; unlike `run`, it
+    /// has no `--max-cycles` since a human is driving it.
+    pub(crate) fn run_until_break(&mut self) -> Result<StepOutcome, VMError> {
+        loop {
+            self.tick_timer()?;
+            self.tick_keyboard()?;
+
+            let outcome = self.step()?;
+            if outcome.opcode.is_none()
+                || self.watchpoint_hit(&outcome).is_some()
+                || self.has_breakpoint(self.pc() as u16)
+            {
+                return Ok(outcome);
+            }
+        }
+    }
+
+    fn watchpoint_hit(&self, outcome: &StepOutcome) -> Option<u16> {
+        outcome
+            .changed_memory
+            .iter()
+            .map(|(addr, _, _)| *addr)
+            .find(|addr| self.has_watchpoint(*addr))
+    }
+
+    /// Executes exactly one fetch-decode-execute cycle. `StepOutcome.opcode`
+    /// is `None` if the program halted (either because the fetched word
+    /// didn't decode or a prior `HALT` stopped the VM). Used both by `run`
+    /// and by the `--debug` REPL's `step` command.
+    pub(crate) fn step(&mut self) -> Result<StepOutcome, VMError> {
+        let pc_before = self.pc() as u16;
+        if !self.running {
+            return Ok(StepOutcome {
+                opcode: None,
+                pc_before,
+                pc_after: pc_before,
+                changed_registers: Vec::new(),
+                changed_memory: Vec::new(),
+            });
+        }
 
-            // Execute
-            self.execute(opcode);
+        let registers_before = self.registers;
+        self.last_mem_write = None;
+
+        // Fetch
+        let instruction = self.fetch()?;
+        self.advance_pc();
+
+        // Decode
+        let Ok(opcode) = Opcode::try_from(instruction) else {
+            println!("ERR: {instruction} not recognized!");
+            self.running = false;
+            return Ok(StepOutcome {
+                opcode: None,
+                pc_before,
+                pc_after: self.pc() as u16,
+                changed_registers: changed_registers(&registers_before, &self.registers),
+                changed_memory: Vec::new(),
+            });
+        };
+
+        // Execute
+        let executed = opcode.clone();
+        self.execute(opcode)?;
+        if self.trace {
             dbg!(&self);
         }
+        Ok(StepOutcome {
+            opcode: Some(executed),
+            pc_before,
+            pc_after: self.pc() as u16,
+            changed_registers: changed_registers(&registers_before, &self.registers),
+            changed_memory: self.last_mem_write.take().into_iter().collect(),
+        })
+    }
+
+    pub(crate) fn is_running(&self) -> bool {
+        self.running
+    }
+
+    pub(crate) fn add_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.insert(addr);
+    }
+
+    pub(crate) fn has_breakpoint(&self, addr: u16) -> bool {
+        self.breakpoints.contains(&addr)
+    }
+
+    pub(crate) fn add_watchpoint(&mut self, addr: u16) {
+        self.watchpoints.insert(addr);
+    }
+
+    pub(crate) fn has_watchpoint(&self, addr: u16) -> bool {
+        self.watchpoints.contains(&addr)
+    }
+
+    pub(crate) fn set_trace(&mut self, enabled: bool) {
+        self.trace = enabled;
     }
 
-    fn reg(&self, idx: usize) -> u16 {
+    pub(crate) fn registers_snapshot(&self) -> [u16; 9] {
+        self.registers
+    }
+
+    pub(crate) fn psr(&self) -> u16 {
+        self.psr
+    }
+
+    pub(crate) fn mem_read(&self, addr: usize) -> u16 {
+        self.memory.get(addr).copied().unwrap_or(0)
+    }
+
+    pub(crate) fn pc(&self) -> usize {
+        self.reg(REG_IDX_PC).expect("REG_IDX_PC is a valid register index") as usize
+    }
+
+    fn reg(&self, idx: usize) -> Result<u16, VMError> {
         self.registers
             .get(idx)
             .copied()
-            .unwrap_or_else(|| panic!("{}", VMError::RegisterIndexOutOfBounds(idx)))
+            .ok_or(VMError::RegisterIndexOutOfBounds(idx))
     }
 
-    fn reg_set(&mut self, idx: usize, val: u16) {
+    fn reg_set(&mut self, idx: usize, val: u16) -> Result<(), VMError> {
         let reg = self.registers
             .get_mut(idx)
-            .unwrap_or_else(|| panic!("{}", VMError::RegisterIndexOutOfBounds(idx)));
+            .ok_or(VMError::RegisterIndexOutOfBounds(idx))?;
         *reg = val;
+        Ok(())
     }
 
-    fn fetch(&self) -> u16 {
+    fn fetch(&self) -> Result<u16, VMError> {
         let pc = self.pc();
-        *self.memory.get(pc).unwrap_or_else(|| panic!("{}", VMError::FetchOutOfBounds(pc)))
+        self.memory
+            .get(pc)
+            .copied()
+            .ok_or(VMError::FetchOutOfBounds(pc))
     }
 
     fn cond_flag(&self) -> ConditionFlag {
-        let r = self.reg(REG_IDX_COND);
+        let r = self.psr & 0b111;
         if r == 1 << 0 {
             ConditionFlag::Pos
         } else if r == 1 << 1 {
@@ -117,11 +455,16 @@ impl VM {
             ConditionFlag::Neg => 1 << 2,
             ConditionFlag::None => 0,
         };
-        self.reg_set(REG_IDX_COND, flag);
+        self.psr = (self.psr & !0b111) | flag;
     }
 
-    fn read_data_into_memory(&mut self, data: &[u8]) {
-        let origin = join_u8(data[0], data[1]) as usize;
+    /// Loads `data` into memory, returning the origin it was loaded at.
+    /// `origin_override` takes precedence over the origin embedded in the
+    /// first word of `data`.
+    fn read_data_into_memory(&mut self, data: &[u8], origin_override: Option<u16>) -> usize {
+        let origin = origin_override
+            .map(|o| o as usize)
+            .unwrap_or_else(|| join_u8(data[0], data[1]) as usize);
         eprintln!("Loading data at origin {:#x}", origin);
         let mut mem_i: usize = origin;
         let mut data_i: usize = 2; // Skip the origin
@@ -131,18 +474,21 @@ impl VM {
             mem_i += 1;
             data_i += 2;
         }
+        origin
     }
 
-    fn execute(&mut self, opcode: Opcode) {
+    fn execute(&mut self, opcode: Opcode) -> Result<(), VMError> {
         match opcode {
             Opcode::ADD {
                 dr,
                 sr1,
                 sr2: Argument::Reg(sr2),
             } => {
-                let res = u16::wrapping_add(self.reg(sr1), self.reg(sr2));
-                eprintln!("ADD reg[{dr}] <- reg[{sr1}] + reg[{sr2}] = {res}");
-                self.reg_set(dr, res);
+                let res = u16::wrapping_add(self.reg(sr1)?, self.reg(sr2)?);
+                if self.trace {
+                    eprintln!("ADD reg[{dr}] <- reg[{sr1}] + reg[{sr2}] = {res}");
+                }
+                self.reg_set(dr, res)?;
                 self.set_flags(res as i16);
             }
             Opcode::ADD {
@@ -150,9 +496,11 @@ impl VM {
                 sr1,
                 sr2: Argument::Immediate(val),
             } => {
-                let res = u16::wrapping_add(self.reg(sr1), val as u16);
-                eprintln!("ADD reg[{dr}] <- reg[{sr1}] + {val} = {res}");
-                self.reg_set(dr, res);
+                let res = u16::wrapping_add(self.reg(sr1)?, val as u16);
+                if self.trace {
+                    eprintln!("ADD reg[{dr}] <- reg[{sr1}] + {val} = {res}");
+                }
+                self.reg_set(dr, res)?;
                 self.set_flags(res as i16);
             }
             Opcode::AND {
@@ -160,12 +508,14 @@ impl VM {
                 sr1,
                 sr2: Argument::Reg(sr2),
             } => {
-                let res = self.reg(sr1) & self.reg(sr2);
-                eprintln!(
-                    "AND reg[{}] <- reg[{}] & reg[{}] = {:#0x}",
-                    dr, sr1, sr2, res
-                );
-                self.reg_set(dr, res);
+                let res = self.reg(sr1)? & self.reg(sr2)?;
+                if self.trace {
+                    eprintln!(
+                        "AND reg[{}] <- reg[{}] & reg[{}] = {:#0x}",
+                        dr, sr1, sr2, res
+                    );
+                }
+                self.reg_set(dr, res)?;
                 self.set_flags(res as i16);
             }
             Opcode::AND {
@@ -173,12 +523,14 @@ impl VM {
                 sr1,
                 sr2: Argument::Immediate(val),
             } => {
-                let res = ((self.reg(sr1) as i16) & val) as u16;
-                eprintln!(
-                    "AND reg[{}] <- reg[{}] & {:#0x} = {:#0x}",
-                    dr, sr1, val, res
-                );
-                self.reg_set(dr, res);
+                let res = ((self.reg(sr1)? as i16) & val) as u16;
+                if self.trace {
+                    eprintln!(
+                        "AND reg[{}] <- reg[{}] & {:#0x} = {:#0x}",
+                        dr, sr1, val, res
+                    );
+                }
+                self.reg_set(dr, res)?;
                 self.set_flags(res as i16);
             }
             Opcode::BR { n, z, p, offset } => {
@@ -187,124 +539,177 @@ impl VM {
                     ((true, _, _), ConditionFlag::Neg)
                     | ((_, true, _), ConditionFlag::Zero)
                     | ((_, _, true), ConditionFlag::Pos) => {
-                        eprintln!("BR: Taken, n={n}, z={z}, p={p} | offset = {offset}");
+                        if self.trace {
+                            eprintln!("BR: Taken, n={n}, z={z}, p={p} | offset = {offset}");
+                        }
                         self.set_pc(self.pc_with_offset(offset));
                     }
-                    _ => eprintln!("BR: Not Taken, n={n}, z={z}, p={p} | offset = {offset}"),
+                    _ => {
+                        if self.trace {
+                            eprintln!("BR: Not Taken, n={n}, z={z}, p={p} | offset = {offset}");
+                        }
+                    }
                 }
             }
             Opcode::JMP { base_r } => {
-                eprintln!("JMP {:#0x}", base_r);
-                self.set_pc(self.reg(base_r) as usize);
+                if self.trace {
+                    eprintln!("JMP {:#0x}", base_r);
+                }
+                self.set_pc(self.reg(base_r)? as usize);
             }
             Opcode::RET => {
-                let dir = self.reg(REG_RET) as usize;
-                eprintln!("RET {:#0x}", dir);
+                let dir = self.reg(REG_RET)? as usize;
+                if self.trace {
+                    eprintln!("RET {:#0x}", dir);
+                }
                 self.set_pc(dir);
             }
             Opcode::JSR { offset } => {
-                self.reg_set(REG_RET, self.pc() as u16);
+                self.reg_set(REG_RET, self.pc() as u16)?;
                 let dir = self.pc_with_offset(offset);
-                eprintln!("JSR {:#0x}+{} = {:#0x}", self.pc(), offset, dir);
+                if self.trace {
+                    eprintln!("JSR {:#0x}+{} = {:#0x}", self.pc(), offset, dir);
+                }
                 self.set_pc(dir);
             }
             Opcode::JSRR { base_r } => {
-                self.reg_set(REG_RET, self.pc() as u16);
-                eprintln!("JSRR {:#0x}", self.reg(base_r));
-                self.set_pc(self.reg(base_r) as usize);
+                self.reg_set(REG_RET, self.pc() as u16)?;
+                if self.trace {
+                    eprintln!("JSRR {:#0x}", self.reg(base_r)?);
+                }
+                self.set_pc(self.reg(base_r)? as usize);
             }
             Opcode::LD { dr, offset } => {
-                let res = self.read_with_offset(offset);
-                eprintln!("LD reg[{dr}] <- {res}");
-                self.reg_set(dr, res);
+                let res = self.read_with_offset(offset)?;
+                if self.trace {
+                    eprintln!("LD reg[{dr}] <- {res}");
+                }
+                self.reg_set(dr, res)?;
                 self.set_flags(res as i16);
             }
             Opcode::LDI { dr, offset } => {
-                let dir = self.read_with_offset(offset) as usize;
-                let res = self.read(dir);
-                eprintln!(
-                    "LDI reg[{}] <- mem[{:#0x}+{}={:#0x}] = {:#0x}",
-                    dr,
-                    self.pc(),
-                    offset,
-                    dir,
-                    res
-                );
-                self.reg_set(dr, res);
+                let dir = self.read_with_offset(offset)? as usize;
+                let res = self.read(dir)?;
+                if self.trace {
+                    eprintln!(
+                        "LDI reg[{}] <- mem[{:#0x}+{}={:#0x}] = {:#0x}",
+                        dr,
+                        self.pc(),
+                        offset,
+                        dir,
+                        res
+                    );
+                }
+                self.reg_set(dr, res)?;
                 self.set_flags(res as i16);
             }
             Opcode::LDR { dr, base_r, offset } => {
-                let base_r_dir = self.reg(base_r);
+                let base_r_dir = self.reg(base_r)?;
                 let dir = (base_r_dir as i16).wrapping_add(offset) as usize;
-                let res = self.read(dir);
-                eprintln!(
-                    "LDR reg[{}] <- mem[{:#0x}+{}={:#0x}] = {:#0x}",
-                    dr, base_r, offset, dir, res
-                );
-                self.reg_set(dr, res);
+                let res = self.read(dir)?;
+                if self.trace {
+                    eprintln!(
+                        "LDR reg[{}] <- mem[{:#0x}+{}={:#0x}] = {:#0x}",
+                        dr, base_r, offset, dir, res
+                    );
+                }
+                self.reg_set(dr, res)?;
                 self.set_flags(res as i16);
             }
             Opcode::LEA { dr, offset } => {
                 let dir = self.pc_with_offset(offset) as u16;
-                eprintln!("LEA reg[{}] <- {:#0x}", dr, dir);
-                self.reg_set(dr, dir);
+                if self.trace {
+                    eprintln!("LEA reg[{}] <- {:#0x}", dr, dir);
+                }
+                self.reg_set(dr, dir)?;
                 self.set_flags(dir as i16);
             }
             Opcode::NOT { dr, sr } => {
-                let res = !self.reg(sr);
-                eprintln!("NOT reg[{}] <- !reg[{}] = {:#0x}", dr, sr, res);
-                self.reg_set(dr, res);
+                let res = !self.reg(sr)?;
+                if self.trace {
+                    eprintln!("NOT reg[{}] <- !reg[{}] = {:#0x}", dr, sr, res);
+                }
+                self.reg_set(dr, res)?;
                 self.set_flags(res as i16);
             }
             Opcode::RTI => {
-                eprintln!("RTI");
-                dbg!(&opcode);
+                if self.is_user_mode() {
+                    if self.trace {
+                        eprintln!("RTI: privilege-mode exception (executed in user mode)");
+                    }
+                    self.enter_vectored(EXCEPTION_VECTOR_TABLE_BASE, PRIVILEGE_MODE_VIOLATION_VECTOR)?;
+                } else {
+                    let pc = self.pop_supervisor_stack()?;
+                    let psr = self.pop_supervisor_stack()?;
+                    self.set_pc(pc as usize);
+                    self.psr = psr;
+                    if self.is_user_mode() {
+                        self.saved_ssp = self.reg(REG_SP)?;
+                        self.reg_set(REG_SP, self.saved_usp)?;
+                    }
+                    if self.trace {
+                        eprintln!("RTI -> {:#0x}", pc);
+                    }
+                }
             }
             Opcode::ST { sr, offset } => {
                 let dir = self.pc_with_offset(offset);
-                let val = self.reg(sr);
-                eprintln!(
-                    "ST mem[{:#0x}+{:#0x} = {:#0x}] <- reg[{}] = {:#0x}",
-                    self.pc(),
-                    offset,
-                    dir,
-                    sr,
-                    val
-                );
-                self.memory[dir] = val;
+                let val = self.reg(sr)?;
+                if self.trace {
+                    eprintln!(
+                        "ST mem[{:#0x}+{:#0x} = {:#0x}] <- reg[{}] = {:#0x}",
+                        self.pc(),
+                        offset,
+                        dir,
+                        sr,
+                        val
+                    );
+                }
+                self.write(dir, val);
             }
             Opcode::STI { sr, offset } => {
-                let dir = self.read_with_offset(offset) as usize;
-                let val = self.reg(sr);
-                eprintln!("STI mem[{:#0x}] <- reg[{}] = {:#0x}", dir, sr, val);
-                self.memory[dir] = val;
+                let dir = self.read_with_offset(offset)? as usize;
+                let val = self.reg(sr)?;
+                if self.trace {
+                    eprintln!("STI mem[{:#0x}] <- reg[{}] = {:#0x}", dir, sr, val);
+                }
+                self.write(dir, val);
             }
             Opcode::STR { sr, base_r, offset } => {
-                let base_r_dir = self.reg(base_r);
+                let base_r_dir = self.reg(base_r)?;
                 let dir = (base_r_dir as i16).wrapping_add(offset) as usize;
-                let val = self.reg(sr);
-                eprintln!("STR mem[{:#0x}] <- reg[{}] = {:#0x}", dir, sr, val);
-                self.memory[dir] = val;
+                let val = self.reg(sr)?;
+                if self.trace {
+                    eprintln!("STR mem[{:#0x}] <- reg[{}] = {:#0x}", dir, sr, val);
+                }
+                self.write(dir, val);
             }
-            Opcode::TRAP { trap_code } => {
-                eprintln!("TRAP {:?}", trap_code);
-                self.reg_set(REG_RET, self.pc() as u16);
-                self.handle_trap_code(trap_code);
+            Opcode::TRAP { vector } => {
+                if self.trace {
+                    eprintln!("TRAP {:#04x}", vector);
+                }
+                // Trap vectors live at mem[vector] (table base 0), unlike the
+                // exception/interrupt table at EXCEPTION_VECTOR_TABLE_BASE;
+                // reusing `enter_vectored` saves PSR/PC on the supervisor
+                // stack and enters supervisor mode, so the service routine
+                // returns to the caller (in whatever mode it ran in) via
+                // `RTI` rather than `RET`.
+                self.enter_vectored(0, vector as u16)?;
             }
             Opcode::RESERVED => {
-                dbg!(&opcode);
-                panic!("Reserved Instruction");
+                if self.trace {
+                    eprintln!("RESERVED instruction executed");
+                }
+                return Err(VMError::ReservedInstruction);
             }
         }
-    }
-
-    fn pc(&self) -> usize {
-        self.reg(REG_IDX_PC) as usize
+        Ok(())
     }
 
     fn set_pc(&mut self, new_pc: usize) {
         let new_pc = u16::try_from(new_pc).unwrap_or_else(|_| panic!("{}", VMError::PcOutOfBounds(new_pc)));
-        self.reg_set(REG_IDX_PC, new_pc);
+        self.reg_set(REG_IDX_PC, new_pc)
+            .expect("REG_IDX_PC is a valid register index");
     }
 
     fn advance_pc(&mut self) {
@@ -316,25 +721,38 @@ impl VM {
         pc.wrapping_add_signed(offset) as usize
     }
 
-    fn read(&mut self, position: usize) -> u16 {
-        if position == MR_KBSR {
-            self.handle_keyboard();
+    fn read(&mut self, position: usize) -> Result<u16, VMError> {
+        let addr = u16::try_from(position).map_err(|_| VMError::MemReadOutOfBounds(position))?;
+        for device in &mut self.devices {
+            if let Some(val) = device.read(addr) {
+                return Ok(val);
+            }
         }
-        *self.memory.get(position).unwrap_or_else(|| panic!("{}", VMError::MemReadOutOfBounds(position)))
+        self.memory
+            .get(position)
+            .copied()
+            .ok_or(VMError::MemReadOutOfBounds(position))
     }
 
-    fn read_with_offset(&mut self, offset: i16) -> u16 {
+    fn read_with_offset(&mut self, offset: i16) -> Result<u16, VMError> {
         self.read(self.pc_with_offset(offset))
     }
 
-    fn handle_keyboard(&mut self) {
-        let mut buffer = [0; 1];
-        io::stdin().read_exact(&mut buffer).unwrap();
-        if buffer[0] != 0 {
-            self.memory[MR_KBSR] = 1 << 15;
-            self.memory[MR_KBDR] = buffer[0] as u16;
-        } else {
-            self.memory[MR_KBSR] = 0;
+    fn write(&mut self, position: usize, val: u16) {
+        if let Ok(addr) = u16::try_from(position) {
+            for device in &mut self.devices {
+                if device.write(addr, val) {
+                    return;
+                }
+            }
+        }
+        let old = self.memory[position];
+        self.memory[position] = val;
+        if let Ok(addr) = u16::try_from(position) {
+            self.last_mem_write = Some((addr, old, val));
+            if addr == MCR && val & MCR_RUN_BIT == 0 {
+                self.running = false;
+            }
         }
     }
 
@@ -346,79 +764,23 @@ impl VM {
         };
         self.set_cond_flag(cond);
     }
-
-    fn handle_trap_code(&mut self, trap_code: TrapCode) {
-        match trap_code {
-            TrapCode::Getc => {
-                let mut buffer = [0; 1];
-                io::stdin().read_exact(&mut buffer).unwrap();
-                self.reg_set(0, buffer[0] as u16);
-                self.set_flags(self.reg(0) as i16);
-            }
-            TrapCode::Out => {
-                let ch = self.reg(0) as u8;
-                print!("{}", ch as char);
-                eprint!("{}", ch as char);
-                io::stdout().flush().unwrap_or_else(|_| panic!("{}", VMError::FlushFailed));
-            }
-            TrapCode::Puts => {
-                let mut i = self.reg(0) as usize;
-                while self.memory[i] != 0x0000 {
-                    let ch = self.memory[i] as u8;
-                    print!("{}", ch as char);
-                    eprint!("{}", ch as char);
-                    i += 1;
-                }
-                io::stdout().flush().unwrap_or_else(|_| panic!("{}", VMError::FlushFailed));
-            }
-            TrapCode::In => {
-                println!("Enter a character: ");
-                io::stdout().flush().unwrap_or_else(|_| panic!("{}", VMError::FlushFailed));
-                let char = io::stdin()
-                    .bytes()
-                    .next()
-                    .and_then(|result| result.ok())
-                    .map(|byte| byte as u16)
-                    .unwrap();
-                self.reg_set(0, char);
-                self.set_flags(self.reg(0) as i16);
-            }
-            TrapCode::Putsp => {
-                let mut i = self.reg(0) as usize;
-                while self.memory[i] != 0x0000 {
-                    let ch = self.memory[i];
-                    let (ch1, ch2) = (ch & 0xFF, ch >> 8);
-                    print!("{}", (ch1 as u8) as char);
-                    eprint!("{}", (ch1 as u8) as char);
-                    if ch2 != 0x00 {
-                        print!("{}", (ch2 as u8) as char);
-                        eprint!("{}", (ch2 as u8) as char);
-                    }
-                    i += 1;
-                }
-            }
-            TrapCode::Halt => {
-                self.running = false;
-            }
-        }
-    }
 }
 
 impl Debug for VM {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         writeln!(
             f,
-            "[r0={},r1={},r2={},r3={},r4={},r5={},r6={},r7={},PC={:#X},COND={:#X}]",
-            self.reg(0),
-            self.reg(1),
-            self.reg(2),
-            self.reg(3),
-            self.reg(4),
-            self.reg(5),
-            self.reg(6),
-            self.reg(7),
-            self.reg(8),
-            self.reg(9),
+            "[r0={},r1={},r2={},r3={},r4={},r5={},r6={},r7={},PC={:#X},PSR={:#X}]",
+            self.registers[0],
+            self.registers[1],
+            self.registers[2],
+            self.registers[3],
+            self.registers[4],
+            self.registers[5],
+            self.registers[6],
+            self.registers[7],
+            self.registers[8],
+            self.psr,
         )
     }
 }
@@ -427,6 +789,36 @@ impl Debug for VM {
 mod tests {
     use super::*;
 
+    fn default_config() -> Config {
+        Config {
+            filename: String::new(),
+            trace: false,
+            origin: None,
+            max_cycles: None,
+            debug: false,
+            os: None,
+            timer_quotient: 0,
+        }
+    }
+
+    struct NullConsole;
+
+    impl Console for NullConsole {
+        fn key_pressed(&mut self) -> bool {
+            false
+        }
+
+        fn read_char(&mut self) -> Option<u8> {
+            None
+        }
+
+        fn write_byte(&mut self, _byte: u8) {}
+    }
+
+    fn test_console() -> Box<dyn Console> {
+        Box::new(NullConsole)
+    }
+
     #[test]
     fn test_le_to_be() {
         let data: Vec<u8> = vec![0xca, 0xfe];
@@ -438,154 +830,175 @@ mod tests {
     fn test_read_data_in_le() {
         // Memory offset is 0x3000
         let data: Vec<u8> = vec![0x30, 0x00, 0xca, 0xfe, 0xba, 0xbe];
-        let vm = VM::new(&data);
+        let vm = VM::new(&data, &default_config(), test_console());
         for i in 0x3000_usize..0x3000 + 4 {
             println!("{:x?}", vm.memory[i]);
         }
+        // The builtin OS image occupies a handful of cells below 0x3000
+        // (see `os_image::words`); everything else should still be blank.
+        let os_image_addrs: Vec<usize> = crate::os_image::words()
+            .into_iter()
+            .map(|(addr, _)| addr as usize)
+            .collect();
         for i in 0..0x3000 {
-            assert_eq!(vm.memory[i], 0);
+            if !os_image_addrs.contains(&i) {
+                assert_eq!(vm.memory[i], 0);
+            }
         }
         assert_eq!(vm.memory[0x3000], 0xcafe);
         assert_eq!(vm.memory[0x3001], 0xbabe);
     }
 
+    #[test]
+    fn step_runs_without_calling_run_first() {
+        // A freshly-built VM should already be executing (as real hardware
+        // does), so `step`/`run_until_break` work standalone, the way the
+        // `--debug` REPL relies on them to.
+        let data: Vec<u8> = vec![0x30, 0x00, 0x50, 0x20]; // AND R0, R0, #0
+        let mut vm = VM::new(&data, &default_config(), test_console());
+
+        let outcome = vm.step().unwrap();
+        assert!(outcome.opcode.is_some());
+        assert_eq!(vm.pc(), 0x3001);
+    }
+
     #[test]
     fn add_register_simple() {
         let data: Vec<u8> = vec![0x30, 0x00, 0xca, 0xfe, 0xba, 0xbe];
-        let mut vm = VM::new(&data);
+        let mut vm = VM::new(&data, &default_config(), test_console());
 
-        vm.reg_set(0, 10);
-        vm.reg_set(1, 3);
-        vm.reg_set(2, 5);
+        vm.reg_set(0, 10).unwrap();
+        vm.reg_set(1, 3).unwrap();
+        vm.reg_set(2, 5).unwrap();
 
         vm.execute(Opcode::ADD {
             dr: 0,
             sr1: 1,
             sr2: Argument::Reg(2),
-        });
-        assert_eq!(vm.reg(0), 8);
-        assert_eq!(vm.reg(1), 3);
-        assert_eq!(vm.reg(2), 5);
+        }).unwrap();
+        assert_eq!(vm.reg(0).unwrap(), 8);
+        assert_eq!(vm.reg(1).unwrap(), 3);
+        assert_eq!(vm.reg(2).unwrap(), 5);
     }
 
     #[test]
     fn add_register_negative() {
         let data: Vec<u8> = vec![0x30, 0x00, 0xca, 0xfe, 0xba, 0xbe];
-        let mut vm = VM::new(&data);
+        let mut vm = VM::new(&data, &default_config(), test_console());
 
-        vm.reg_set(0, 0);
-        vm.reg_set(1, 0);
-        vm.reg_set(2, 0);
+        vm.reg_set(0, 0).unwrap();
+        vm.reg_set(1, 0).unwrap();
+        vm.reg_set(2, 0).unwrap();
 
         vm.execute(Opcode::ADD {
             dr: 0,
             sr1: 1,
             sr2: Argument::Immediate(-5),
-        });
-        assert_eq!(vm.reg(0), 0b1111_1111_1111_1011);
-        assert_eq!(vm.reg(1), 0);
-        assert_eq!(vm.reg(2), 0);
+        }).unwrap();
+        assert_eq!(vm.reg(0).unwrap(), 0b1111_1111_1111_1011);
+        assert_eq!(vm.reg(1).unwrap(), 0);
+        assert_eq!(vm.reg(2).unwrap(), 0);
     }
 
     #[test]
     fn add_reg_overflow() {
         let data: Vec<u8> = vec![0x30, 0x00, 0xca, 0xfe, 0xba, 0xbe];
-        let mut vm = VM::new(&data);
+        let mut vm = VM::new(&data, &default_config(), test_console());
 
-        vm.reg_set(0, 3);
-        vm.reg_set(1, u16::MAX);
-        vm.reg_set(2, 2);
+        vm.reg_set(0, 3).unwrap();
+        vm.reg_set(1, u16::MAX).unwrap();
+        vm.reg_set(2, 2).unwrap();
 
         vm.execute(Opcode::ADD {
             dr: 0,
             sr1: 1,
             sr2: Argument::Reg(2),
-        });
-        assert_eq!(vm.reg(0), 1);
-        assert_eq!(vm.reg(1), u16::MAX);
-        assert_eq!(vm.reg(2), 2);
+        }).unwrap();
+        assert_eq!(vm.reg(0).unwrap(), 1);
+        assert_eq!(vm.reg(1).unwrap(), u16::MAX);
+        assert_eq!(vm.reg(2).unwrap(), 2);
     }
 
     #[test]
     fn add_imm5_overflow() {
         let data: Vec<u8> = vec![0x30, 0x00, 0xca, 0xfe, 0xba, 0xbe];
-        let mut vm = VM::new(&data);
+        let mut vm = VM::new(&data, &default_config(), test_console());
 
-        vm.reg_set(0, 3);
-        vm.reg_set(1, u16::MAX);
-        vm.reg_set(2, 1);
+        vm.reg_set(0, 3).unwrap();
+        vm.reg_set(1, u16::MAX).unwrap();
+        vm.reg_set(2, 1).unwrap();
 
         vm.execute(Opcode::ADD {
             dr: 0,
             sr1: 1,
             sr2: Argument::Immediate(2),
-        });
-        assert_eq!(vm.reg(0), 1);
-        assert_eq!(vm.reg(1), u16::MAX);
-        assert_eq!(vm.reg(2), 1);
+        }).unwrap();
+        assert_eq!(vm.reg(0).unwrap(), 1);
+        assert_eq!(vm.reg(1).unwrap(), u16::MAX);
+        assert_eq!(vm.reg(2).unwrap(), 1);
     }
 
     #[test]
     fn execute_and_regs() {
         let data: Vec<u8> = vec![0x30, 0x00, 0xca, 0xfe, 0xba, 0xbe];
-        let mut vm = VM::new(&data);
+        let mut vm = VM::new(&data, &default_config(), test_console());
 
-        vm.reg_set(0, 3);
-        vm.reg_set(1, 4);
-        vm.reg_set(2, 7);
+        vm.reg_set(0, 3).unwrap();
+        vm.reg_set(1, 4).unwrap();
+        vm.reg_set(2, 7).unwrap();
 
         vm.execute(Opcode::AND {
             dr: 0,
             sr1: 1,
             sr2: Argument::Reg(1),
-        });
-        assert_eq!(vm.reg(0), 4 & 7);
-        assert_eq!(vm.reg(1), 4);
-        assert_eq!(vm.reg(2), 7);
+        }).unwrap();
+        assert_eq!(vm.reg(0).unwrap(), 4 & 7);
+        assert_eq!(vm.reg(1).unwrap(), 4);
+        assert_eq!(vm.reg(2).unwrap(), 7);
     }
 
     #[test]
     fn execute_and_imm() {
         let data: Vec<u8> = vec![0x30, 0x00, 0xca, 0xfe, 0xba, 0xbe];
-        let mut vm = VM::new(&data);
+        let mut vm = VM::new(&data, &default_config(), test_console());
 
-        vm.reg_set(0, 3);
-        vm.reg_set(1, 4);
-        vm.reg_set(2, 7);
+        vm.reg_set(0, 3).unwrap();
+        vm.reg_set(1, 4).unwrap();
+        vm.reg_set(2, 7).unwrap();
 
         vm.execute(Opcode::AND {
             dr: 0,
             sr1: 1,
             sr2: Argument::Immediate(9),
-        });
-        assert_eq!(vm.reg(0), 4 & 9);
-        assert_eq!(vm.reg(1), 4);
-        assert_eq!(vm.reg(2), 7);
+        }).unwrap();
+        assert_eq!(vm.reg(0).unwrap(), 4 & 9);
+        assert_eq!(vm.reg(1).unwrap(), 4);
+        assert_eq!(vm.reg(2).unwrap(), 7);
     }
 
     #[test]
     fn execute_and_zero() {
         let data: Vec<u8> = vec![0x30, 0x00, 0xca, 0xfe, 0xba, 0xbe];
-        let mut vm = VM::new(&data);
+        let mut vm = VM::new(&data, &default_config(), test_console());
 
-        vm.reg_set(0, 3);
-        vm.reg_set(1, 4);
-        vm.reg_set(2, 7);
+        vm.reg_set(0, 3).unwrap();
+        vm.reg_set(1, 4).unwrap();
+        vm.reg_set(2, 7).unwrap();
 
         vm.execute(Opcode::AND {
             dr: 0,
             sr1: 1,
             sr2: Argument::Immediate(0),
-        });
-        assert_eq!(vm.reg(0), 0);
-        assert_eq!(vm.reg(1), 4);
-        assert_eq!(vm.reg(2), 7);
+        }).unwrap();
+        assert_eq!(vm.reg(0).unwrap(), 0);
+        assert_eq!(vm.reg(1).unwrap(), 4);
+        assert_eq!(vm.reg(2).unwrap(), 7);
     }
 
     #[test]
     fn execute_br_not_taken() {
         let data: Vec<u8> = vec![0x30, 0x00, 0xca, 0xfe, 0xba, 0xbe];
-        let mut vm = VM::new(&data);
+        let mut vm = VM::new(&data, &default_config(), test_console());
 
         assert_eq!(vm.pc(), 0x3000);
         vm.set_cond_flag(ConditionFlag::Neg);
@@ -594,14 +1007,14 @@ mod tests {
             z: false,
             p: true,
             offset: 15,
-        });
+        }).unwrap();
         assert_eq!(vm.pc(), 0x3000);
     }
 
     #[test]
     fn execute_br_taken_pos() {
         let data: Vec<u8> = vec![0x30, 0x00, 0xca, 0xfe, 0xba, 0xbe];
-        let mut vm = VM::new(&data);
+        let mut vm = VM::new(&data, &default_config(), test_console());
 
         assert_eq!(vm.pc(), 0x3000);
         vm.set_cond_flag(ConditionFlag::Pos);
@@ -610,14 +1023,14 @@ mod tests {
             z: false,
             p: true,
             offset: 15,
-        });
+        }).unwrap();
         assert_eq!(vm.pc(), 0x3000 + 15);
     }
 
     #[test]
     fn execute_br_taken_neg() {
         let data: Vec<u8> = vec![0x30, 0x00, 0xca, 0xfe, 0xba, 0xbe];
-        let mut vm = VM::new(&data);
+        let mut vm = VM::new(&data, &default_config(), test_console());
 
         assert_eq!(vm.pc(), 0x3000);
         vm.set_cond_flag(ConditionFlag::Pos);
@@ -626,14 +1039,14 @@ mod tests {
             z: false,
             p: true,
             offset: -15,
-        });
+        }).unwrap();
         assert_eq!(vm.pc(), 0x3000 - 15);
     }
 
     #[test]
     fn execute_br_taken_overflow() {
         let data: Vec<u8> = vec![0x30, 0x00, 0xca, 0xfe, 0xba, 0xbe];
-        let mut vm = VM::new(&data);
+        let mut vm = VM::new(&data, &default_config(), test_console());
 
         vm.set_pc(u16::MAX as usize);
         assert_eq!(vm.pc(), u16::MAX as usize);
@@ -643,14 +1056,14 @@ mod tests {
             z: false,
             p: true,
             offset: 2,
-        });
+        }).unwrap();
         assert_eq!(vm.pc(), 1);
     }
 
     #[test]
     fn execute_br_taken_underflow() {
         let data: Vec<u8> = vec![0x30, 0x00, 0xca, 0xfe, 0xba, 0xbe];
-        let mut vm = VM::new(&data);
+        let mut vm = VM::new(&data, &default_config(), test_console());
 
         vm.set_pc(0);
         assert_eq!(vm.pc(), 0);
@@ -660,60 +1073,60 @@ mod tests {
             z: false,
             p: true,
             offset: -1,
-        });
+        }).unwrap();
         assert_eq!(vm.pc(), u16::MAX as usize);
     }
 
     #[test]
     fn execute_jmp() {
         let data: Vec<u8> = vec![0x30, 0x00, 0xca, 0xfe, 0xba, 0xbe];
-        let mut vm = VM::new(&data);
+        let mut vm = VM::new(&data, &default_config(), test_console());
 
-        vm.reg_set(1, 0x3999);
-        vm.execute(Opcode::JMP { base_r: 1 });
+        vm.reg_set(1, 0x3999).unwrap();
+        vm.execute(Opcode::JMP { base_r: 1 }).unwrap();
         assert_eq!(vm.pc(), 0x3999);
     }
 
     #[test]
     fn execute_ret() {
         let data: Vec<u8> = vec![0x30, 0x00, 0xca, 0xfe, 0xba, 0xbe];
-        let mut vm = VM::new(&data);
+        let mut vm = VM::new(&data, &default_config(), test_console());
 
-        vm.reg_set(7, 0x3999);
-        vm.execute(Opcode::RET);
+        vm.reg_set(7, 0x3999).unwrap();
+        vm.execute(Opcode::RET).unwrap();
         assert_eq!(vm.pc(), 0x3999);
     }
 
     #[test]
     fn execute_jsr_pos() {
         let data: Vec<u8> = vec![0x30, 0x00, 0xca, 0xfe, 0xba, 0xbe];
-        let mut vm = VM::new(&data);
+        let mut vm = VM::new(&data, &default_config(), test_console());
 
         vm.set_pc(0x3005);
-        vm.execute(Opcode::JSR { offset: 15 });
+        vm.execute(Opcode::JSR { offset: 15 }).unwrap();
         assert_eq!(vm.pc(), 0x3005 + 15);
-        assert_eq!(vm.reg(REG_RET), 0x3005);
+        assert_eq!(vm.reg(REG_RET).unwrap(), 0x3005);
     }
 
     #[test]
     fn execute_jsr_neg() {
         let data: Vec<u8> = vec![0x30, 0x00, 0xca, 0xfe, 0xba, 0xbe];
-        let mut vm = VM::new(&data);
+        let mut vm = VM::new(&data, &default_config(), test_console());
 
         vm.set_pc(0x3005);
-        vm.execute(Opcode::JSR { offset: -15 });
+        vm.execute(Opcode::JSR { offset: -15 }).unwrap();
         assert_eq!(vm.pc(), 0x3005 - 15);
-        assert_eq!(vm.reg(REG_RET), 0x3005);
+        assert_eq!(vm.reg(REG_RET).unwrap(), 0x3005);
     }
 
     #[test]
     fn execute_jsrr() {
         let data: Vec<u8> = vec![0x30, 0x00, 0xca, 0xfe, 0xba, 0xbe];
-        let mut vm = VM::new(&data);
+        let mut vm = VM::new(&data, &default_config(), test_console());
 
         vm.set_pc(0x3005);
-        vm.reg_set(2, 0x4000);
-        vm.execute(Opcode::JSRR { base_r: 2 });
+        vm.reg_set(2, 0x4000).unwrap();
+        vm.execute(Opcode::JSRR { base_r: 2 }).unwrap();
         assert_eq!(vm.pc(), 0x4000);
         assert_eq!(vm.registers[REG_RET], 0x3005);
     }
@@ -721,38 +1134,174 @@ mod tests {
     #[test]
     fn execute_ld_pos() {
         let data: Vec<u8> = vec![0x30, 0x00, 0x10, 0x10, 0xca, 0xfe, 0xba, 0xbe];
-        let mut vm = VM::new(&data);
+        let mut vm = VM::new(&data, &default_config(), test_console());
 
-        vm.execute(Opcode::LD { dr: 0, offset: 2 });
-        assert_eq!(vm.reg(0), 0xbabe);
+        vm.execute(Opcode::LD { dr: 0, offset: 2 }).unwrap();
+        assert_eq!(vm.reg(0).unwrap(), 0xbabe);
     }
 
     #[test]
     fn execute_ld_neg() {
         let data: Vec<u8> = vec![0x30, 0x00, 0x10, 0x10, 0xca, 0xfe, 0xba, 0xbe];
-        let mut vm = VM::new(&data);
+        let mut vm = VM::new(&data, &default_config(), test_console());
 
         vm.set_pc(0x3000 + 2);
-        vm.execute(Opcode::LD { dr: 0, offset: -1 });
-        assert_eq!(vm.reg(0), 0xcafe);
+        vm.execute(Opcode::LD { dr: 0, offset: -1 }).unwrap();
+        assert_eq!(vm.reg(0).unwrap(), 0xcafe);
     }
 
     #[test]
     fn execute_ldi_pos() {
         let data: Vec<u8> = vec![0x30, 0x00, 0x30, 0x02, 0xca, 0xfe, 0xba, 0xbe];
-        let mut vm = VM::new(&data);
+        let mut vm = VM::new(&data, &default_config(), test_console());
+
+        vm.execute(Opcode::LDI { dr: 0, offset: 0 }).unwrap();
+        assert_eq!(vm.reg(0).unwrap(), 0xbabe);
+    }
+
+    #[test]
+    fn execute_rti_restores_pc_and_psr() {
+        let data: Vec<u8> = vec![0x30, 0x00, 0xca, 0xfe, 0xba, 0xbe];
+        let mut vm = VM::new(&data, &default_config(), test_console());
+
+        vm.push_supervisor_stack(PSR_PRIVILEGE_BIT | (1 << 0)).unwrap(); // user mode, cond=Pos
+        vm.push_supervisor_stack(0x4000).unwrap();
+        vm.execute(Opcode::RTI).unwrap();
+        assert_eq!(vm.pc(), 0x4000);
+        assert!(vm.is_user_mode());
+        assert_eq!(vm.cond_flag(), ConditionFlag::Pos);
+    }
+
+    #[test]
+    fn execute_rti_swaps_stack_back_to_user_on_return() {
+        let data: Vec<u8> = vec![0x30, 0x00, 0xca, 0xfe, 0xba, 0xbe];
+        let mut vm = VM::new(&data, &default_config(), test_console());
+
+        vm.saved_usp = 0xFE00;
+        let ssp_before_push = vm.reg(REG_SP).unwrap();
+        vm.push_supervisor_stack(PSR_PRIVILEGE_BIT).unwrap();
+        vm.push_supervisor_stack(0x4000).unwrap();
+        vm.execute(Opcode::RTI).unwrap();
+        assert_eq!(vm.reg(REG_SP).unwrap(), 0xFE00);
+        assert_eq!(vm.saved_ssp, ssp_before_push);
+    }
+
+    #[test]
+    fn execute_rti_in_user_mode_faults() {
+        let data: Vec<u8> = vec![0x30, 0x00, 0xca, 0xfe, 0xba, 0xbe];
+        let mut vm = VM::new(&data, &default_config(), test_console());
+        vm.memory[EXCEPTION_VECTOR_TABLE_BASE as usize] = 0x5000;
+
+        vm.psr |= PSR_PRIVILEGE_BIT;
+        vm.set_pc(0x4000);
+        vm.execute(Opcode::RTI).unwrap();
+
+        assert_eq!(vm.pc(), 0x5000);
+        assert!(!vm.is_user_mode());
+    }
+
+    #[test]
+    fn execute_trap_enters_supervisor_mode_and_returns_via_rti() {
+        let data: Vec<u8> = vec![0x30, 0x00, 0xca, 0xfe, 0xba, 0xbe];
+        let mut vm = VM::new(&data, &default_config(), test_console());
+        vm.psr |= PSR_PRIVILEGE_BIT;
+        vm.set_pc(0x3005);
+        vm.memory[0x25] = 0x4000; // HALT's trap vector (x25) -> service routine
+
+        vm.execute(Opcode::TRAP { vector: 0x25 }).unwrap();
+        assert_eq!(vm.pc(), 0x4000);
+        assert!(!vm.is_user_mode());
 
-        vm.execute(Opcode::LDI { dr: 0, offset: 0 });
-        assert_eq!(vm.reg(0), 0xbabe);
+        vm.execute(Opcode::RTI).unwrap();
+        assert_eq!(vm.pc(), 0x3005);
+        assert!(vm.is_user_mode());
+    }
+
+    #[test]
+    fn timer_interrupt_preempts_user_loop_and_returns_via_rti() {
+        // 0x3000: BR n,z,p #-1, an infinite self-loop.
+        let data: Vec<u8> = vec![0x30, 0x00, 0x0f, 0xff];
+        let mut config = default_config();
+        config.timer_quotient = 1;
+        let mut vm = VM::new(&data, &config, test_console());
+        vm.psr |= PSR_PRIVILEGE_BIT;
+        vm.set_cond_flag(ConditionFlag::Zero); // matches the loop's BR n,z,p
+        vm.memory[(EXCEPTION_VECTOR_TABLE_BASE + TIMER_INTERRUPT_VECTOR) as usize] = 0x4000;
+        vm.memory[0x4000] = Opcode::RTI.encode();
+        vm.timer.borrow_mut().enabled = true;
+
+        // The user loop keeps branching back to itself.
+        vm.step().unwrap();
+        assert_eq!(vm.pc(), 0x3000);
+        assert!(vm.is_user_mode());
+
+        // The timer fires: control is diverted to the ISR in supervisor mode.
+        vm.tick_timer().unwrap();
+        assert_eq!(vm.pc(), 0x4000);
+        assert!(!vm.is_user_mode());
+
+        // The ISR's RTI hands control straight back to the interrupted loop.
+        vm.step().unwrap();
+        assert_eq!(vm.pc(), 0x3000);
+        assert!(vm.is_user_mode());
     }
 
     #[test]
     fn execute_ldi_neg() {
         let data: Vec<u8> = vec![0x30, 0x00, 0x30, 0x02, 0xca, 0xfe, 0xba, 0xbe];
-        let mut vm = VM::new(&data);
+        let mut vm = VM::new(&data, &default_config(), test_console());
 
         vm.set_pc(0x3000 + 1);
-        vm.execute(Opcode::LDI { dr: 0, offset: -1 });
-        assert_eq!(vm.reg(0), 0xbabe);
+        vm.execute(Opcode::LDI { dr: 0, offset: -1 }).unwrap();
+        assert_eq!(vm.reg(0).unwrap(), 0xbabe);
+    }
+
+    #[test]
+    fn reg_out_of_bounds_is_an_error() {
+        let data: Vec<u8> = vec![0x30, 0x00, 0xca, 0xfe, 0xba, 0xbe];
+        let vm = VM::new(&data, &default_config(), test_console());
+
+        assert!(matches!(
+            vm.reg(42),
+            Err(VMError::RegisterIndexOutOfBounds(42))
+        ));
+    }
+
+    #[test]
+    fn reg_set_out_of_bounds_is_an_error() {
+        let data: Vec<u8> = vec![0x30, 0x00, 0xca, 0xfe, 0xba, 0xbe];
+        let mut vm = VM::new(&data, &default_config(), test_console());
+
+        assert!(matches!(
+            vm.reg_set(42, 1),
+            Err(VMError::RegisterIndexOutOfBounds(42))
+        ));
+    }
+
+    #[test]
+    fn execute_ldr_out_of_bounds_address_is_an_error() {
+        let data: Vec<u8> = vec![0x30, 0x00, 0xca, 0xfe, 0xba, 0xbe];
+        let mut vm = VM::new(&data, &default_config(), test_console());
+
+        // base_r holds 0, offset wraps the computed address negative, which
+        // turns into a huge out-of-range usize once cast.
+        vm.reg_set(1, 0).unwrap();
+        let result = vm.execute(Opcode::LDR {
+            dr: 0,
+            base_r: 1,
+            offset: -1,
+        });
+        assert!(matches!(result, Err(VMError::MemReadOutOfBounds(_))));
+    }
+
+    #[test]
+    fn execute_reserved_is_an_error() {
+        let data: Vec<u8> = vec![0x30, 0x00, 0xca, 0xfe, 0xba, 0xbe];
+        let mut vm = VM::new(&data, &default_config(), test_console());
+
+        assert!(matches!(
+            vm.execute(Opcode::RESERVED),
+            Err(VMError::ReservedInstruction)
+        ));
     }
 }